@@ -0,0 +1,190 @@
+use crate::game::Game;
+use crate::policy::{self, Policy};
+use crate::rng::SplitMix64;
+
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The result of a single benchmark game.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+pub struct GameResult {
+    pub seed: u64,
+    pub score: u32,
+    pub highest_tile: u32,
+    pub moves: usize,
+}
+
+/// Plays `games` games on boards of `size` in parallel, each with a policy
+/// built by `policy` from the seed of the game.
+/// The seeds of the games are drawn from `seed`, so the results are reproducible
+/// as long as the policies are.
+pub fn run<F>(games: usize, size: usize, seed: u64, policy: F) -> Vec<GameResult>
+where
+    F: Fn(u64) -> Box<dyn Policy> + Sync,
+{
+    let mut rng = SplitMix64::seed_from_u64(seed);
+    let seeds = (0..games).map(|_| rng.gen()).collect::<Vec<u64>>();
+    seeds
+        .into_par_iter()
+        .map(|seed| {
+            let mut game = Game::with_size(size, seed);
+            policy::play(&mut policy(seed), &mut game, usize::MAX);
+            GameResult {
+                seed,
+                score: game.score,
+                highest_tile: game.highest_tile().unwrap_or(0),
+                moves: game.moves,
+            }
+        })
+        .collect()
+}
+
+/// Summary statistics over the results of a benchmark.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct BenchStats {
+    pub games: usize,
+    pub mean_score: f64,
+    pub median_score: f64,
+    pub std_score: f64,
+    /// The share of games that reached each tile, from the lowest highest tile
+    /// of any game up to the highest tile.
+    pub reach_rates: BTreeMap<u32, f64>,
+    pub mean_moves: f64,
+    pub games_per_second: f64,
+}
+
+impl BenchStats {
+    /// Computes the statistics of `results`, which took `elapsed` to play.
+    pub fn new(results: &[GameResult], elapsed: Duration) -> Self {
+        let games = results.len();
+        let n = games.max(1) as f64;
+
+        let mut scores = results.iter().map(|r| r.score as f64).collect::<Vec<_>>();
+        scores.sort_by(f64::total_cmp);
+        let mean_score = scores.iter().sum::<f64>() / n;
+        // The two middle scores, which are the same score for an odd number of games.
+        let median_score = match games {
+            0 => 0.0,
+            _ => (scores[(games - 1) / 2] + scores[games / 2]) / 2.0,
+        };
+        let variance = scores.iter().map(|s| (s - mean_score).powi(2)).sum::<f64>() / n;
+
+        let mut reach_rates = BTreeMap::new();
+        let lowest = results.iter().map(|r| r.highest_tile).min().unwrap_or(0);
+        let highest = results.iter().map(|r| r.highest_tile).max().unwrap_or(0);
+        let mut tile = lowest.max(2);
+        while tile <= highest {
+            let reached = results.iter().filter(|r| r.highest_tile >= tile).count();
+            reach_rates.insert(tile, reached as f64 / n);
+            tile *= 2;
+        }
+
+        Self {
+            games,
+            mean_score,
+            median_score,
+            std_score: variance.sqrt(),
+            reach_rates,
+            mean_moves: results.iter().map(|r| r.moves as f64).sum::<f64>() / n,
+            games_per_second: games as f64 / elapsed.as_secs_f64(),
+        }
+    }
+
+    /// Formats the statistics as a human readable table.
+    pub fn table(&self) -> String {
+        let mut table = String::new();
+        let mut row =
+            |name: &str, value: String| writeln!(table, "{name:<16} {value:>12}").unwrap();
+        row("Games", self.games.to_string());
+        row("Mean score", format!("{:.1}", self.mean_score));
+        row("Median score", format!("{:.1}", self.median_score));
+        row("Std score", format!("{:.1}", self.std_score));
+        row("Mean moves", format!("{:.1}", self.mean_moves));
+        row("Games/s", format!("{:.2}", self.games_per_second));
+        for (tile, rate) in &self.reach_rates {
+            row(&format!("Reached {tile}"), format!("{:.1}%", rate * 100.0));
+        }
+        table
+    }
+
+    /// Formats the statistics as a CSV header and a row,
+    /// with a `reach_<tile>` column per tile.
+    pub fn csv(&self) -> String {
+        let mut header = vec![
+            "games".to_string(),
+            "mean_score".to_string(),
+            "median_score".to_string(),
+            "std_score".to_string(),
+            "mean_moves".to_string(),
+            "games_per_second".to_string(),
+        ];
+        let mut row = vec![
+            self.games.to_string(),
+            self.mean_score.to_string(),
+            self.median_score.to_string(),
+            self.std_score.to_string(),
+            self.mean_moves.to_string(),
+            self.games_per_second.to_string(),
+        ];
+        for (tile, rate) in &self.reach_rates {
+            header.push(format!("reach_{tile}"));
+            row.push(rate.to_string());
+        }
+        format!("{}\n{}\n", header.join(","), row.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Random;
+
+    fn result(score: u32, highest_tile: u32, moves: usize) -> GameResult {
+        GameResult {
+            seed: 0,
+            score,
+            highest_tile,
+            moves,
+        }
+    }
+
+    #[test]
+    fn statistics() {
+        let results = [
+            result(100, 64, 10),
+            result(300, 256, 30),
+            result(200, 128, 20),
+            result(400, 256, 40),
+        ];
+        let stats = BenchStats::new(&results, Duration::from_secs(2));
+        assert_eq!(stats.games, 4);
+        assert_eq!(stats.mean_score, 250.0);
+        assert_eq!(stats.median_score, 250.0);
+        assert_eq!(stats.std_score, 12500f64.sqrt());
+        assert_eq!(stats.mean_moves, 25.0);
+        assert_eq!(stats.games_per_second, 2.0);
+        assert_eq!(
+            stats.reach_rates,
+            BTreeMap::from([(64, 1.0), (128, 0.75), (256, 0.5)])
+        );
+
+        let csv = stats.csv();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with("reach_64,reach_128,reach_256"));
+        assert!(lines[1].starts_with("4,250,250,"));
+    }
+
+    #[test]
+    fn reproducible_runs() {
+        let random = |seed| Box::new(Random::new(seed)) as Box<dyn Policy>;
+        let results = run(8, 3, 1, random);
+        assert_eq!(results.len(), 8);
+        assert_eq!(results, run(8, 3, 1, random));
+        assert!(results.iter().all(|r| r.moves > 0 && r.highest_tile >= 4));
+    }
+}
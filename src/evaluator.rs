@@ -0,0 +1,266 @@
+use crate::game::Game;
+
+use std::sync::Arc;
+
+/// Scores a board position, higher is better.
+///
+/// The searches use an evaluator to score the positions they reach,
+/// so a strategy can be tuned without changing the search code.
+pub trait Evaluator {
+    fn evaluate(&self, game: &Game) -> f64;
+}
+
+impl Evaluator for fn(&Game) -> f64 {
+    fn evaluate(&self, game: &Game) -> f64 {
+        self(game)
+    }
+}
+
+impl<E: Evaluator + ?Sized> Evaluator for &E {
+    fn evaluate(&self, game: &Game) -> f64 {
+        (**self).evaluate(game)
+    }
+}
+
+impl<E: Evaluator + ?Sized> Evaluator for Box<E> {
+    fn evaluate(&self, game: &Game) -> f64 {
+        (**self).evaluate(game)
+    }
+}
+
+impl<E: Evaluator + ?Sized> Evaluator for Arc<E> {
+    fn evaluate(&self, game: &Game) -> f64 {
+        (**self).evaluate(game)
+    }
+}
+
+/// The score of the game.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Score;
+
+impl Evaluator for Score {
+    fn evaluate(&self, game: &Game) -> f64 {
+        game.score as f64
+    }
+}
+
+/// The number of empty cells.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct EmptyCells;
+
+impl Evaluator for EmptyCells {
+    fn evaluate(&self, game: &Game) -> f64 {
+        game.empty_tiles().len() as f64
+    }
+}
+
+/// Penalises rows and columns that are not sorted, in either direction.
+/// For every line, the smaller of the total increase and the total decrease
+/// of the exponents is subtracted, so a monotonic board scores 0.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Monotonicity;
+
+impl Evaluator for Monotonicity {
+    fn evaluate(&self, game: &Game) -> f64 {
+        let size = game.size();
+        let lines = (0..size).flat_map(|line| {
+            [
+                (0..size).map(|k| game.get(line, k)).collect::<Vec<_>>(),
+                (0..size).map(|k| game.get(k, line)).collect(),
+            ]
+        });
+
+        let mut penalty = 0;
+        for line in lines {
+            let (mut increase, mut decrease) = (0, 0);
+            for pair in line.windows(2) {
+                if pair[1] > pair[0] {
+                    increase += pair[1] - pair[0];
+                } else {
+                    decrease += pair[0] - pair[1];
+                }
+            }
+            penalty += increase.min(decrease);
+        }
+        -(penalty as f64)
+    }
+}
+
+/// Penalises differences between neighbouring tiles, which are harder to merge.
+/// The absolute differences of the exponents of neighbouring non-empty cells are subtracted.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Smoothness;
+
+impl Evaluator for Smoothness {
+    fn evaluate(&self, game: &Game) -> f64 {
+        let size = game.size();
+        let mut penalty = 0;
+        for i in 0..size {
+            for j in 0..size {
+                let value = game.get(i, j);
+                if value == 0 {
+                    continue;
+                }
+                for (x, y) in [(i + 1, j), (i, j + 1)] {
+                    if x < size && y < size && game.get(x, y) != 0 {
+                        penalty += value.abs_diff(game.get(x, y));
+                    }
+                }
+            }
+        }
+        -(penalty as f64)
+    }
+}
+
+/// Rewards keeping the tiles in decreasing order along a snake that starts in a corner,
+/// going along the first row and back along the next.
+/// Every tile adds its value times `ratio` to the power of its position on the snake,
+/// using the corner and direction that scores best.
+#[derive(Clone, Copy, Debug)]
+pub struct CornerSnake {
+    pub ratio: f64,
+}
+
+impl Default for CornerSnake {
+    fn default() -> Self {
+        Self { ratio: 0.5 }
+    }
+}
+
+impl Evaluator for CornerSnake {
+    fn evaluate(&self, game: &Game) -> f64 {
+        let size = game.size();
+        let last = size - 1;
+        let weights = (0..size * size)
+            .map(|k| self.ratio.powi(k as i32))
+            .collect::<Vec<_>>();
+
+        let mut best = f64::NEG_INFINITY;
+        for transpose in [false, true] {
+            for flip_rows in [false, true] {
+                for flip_cols in [false, true] {
+                    let mut value = 0.0;
+                    for i in 0..size {
+                        for j in 0..size {
+                            let exponent = game.get(i, j);
+                            if exponent == 0 {
+                                continue;
+                            }
+                            let (row, col) = if transpose { (j, i) } else { (i, j) };
+                            let row = if flip_rows { last - row } else { row };
+                            let col = if flip_cols { last - col } else { col };
+                            let col = if row % 2 == 0 { col } else { last - col };
+                            value += (1u64 << exponent) as f64 * weights[row * size + col];
+                        }
+                    }
+                    best = best.max(value);
+                }
+            }
+        }
+        best
+    }
+}
+
+/// A weighted sum of evaluators.
+///
+/// The default combines the empty cells, monotonicity, smoothness and the score.
+pub struct Weighted {
+    terms: Vec<(f64, Box<dyn Evaluator + Send + Sync>)>,
+}
+
+impl Default for Weighted {
+    fn default() -> Self {
+        Self::new()
+            .add(1.0, Score)
+            .add(64.0, EmptyCells)
+            .add(32.0, Monotonicity)
+            .add(8.0, Smoothness)
+    }
+}
+
+impl Weighted {
+    /// A sum without terms, which scores every position with 0.
+    pub fn new() -> Self {
+        Self { terms: vec![] }
+    }
+
+    /// Adds `evaluator` to the sum, scaled by `weight`.
+    pub fn add(mut self, weight: f64, evaluator: impl Evaluator + Send + Sync + 'static) -> Self {
+        self.terms.push((weight, Box::new(evaluator)));
+        self
+    }
+}
+
+impl Evaluator for Weighted {
+    fn evaluate(&self, game: &Game) -> f64 {
+        self.terms
+            .iter()
+            .map(|(weight, evaluator)| weight * evaluator.evaluate(game))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_rows<const N: usize>(rows: [[u32; N]; N]) -> Game {
+        let mut game = Game::with_size(N, 0);
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                game.set(i, j, value);
+            }
+        }
+        game
+    }
+
+    #[test]
+    fn empty_cells_and_score() {
+        let mut game = from_rows([[1, 0, 0], [0, 2, 0], [0, 0, 0]]);
+        game.score = 12;
+        assert_eq!(EmptyCells.evaluate(&game), 7.0);
+        assert_eq!(Score.evaluate(&game), 12.0);
+    }
+
+    #[test]
+    fn monotonicity() {
+        let sorted = from_rows([[4, 3, 2], [3, 2, 1], [2, 1, 0]]);
+        assert_eq!(Monotonicity.evaluate(&sorted), 0.0);
+
+        let zigzag = from_rows([[4, 1, 4], [0, 0, 0], [0, 0, 0]]);
+        assert_eq!(Monotonicity.evaluate(&zigzag), -3.0);
+    }
+
+    #[test]
+    fn smoothness() {
+        let game = from_rows([[1, 3, 0], [1, 0, 0], [5, 0, 0]]);
+        assert_eq!(Smoothness.evaluate(&game), -(2.0 + 0.0 + 4.0));
+    }
+
+    #[test]
+    fn corner_snake_prefers_ordered_tiles() {
+        let snake = from_rows([[1, 2, 3], [6, 5, 4], [7, 8, 9]]);
+        let shuffled = from_rows([[1, 9, 3], [6, 5, 4], [7, 8, 2]]);
+        assert!(
+            CornerSnake::default().evaluate(&snake) > CornerSnake::default().evaluate(&shuffled)
+        );
+
+        // Every corner and direction scores the same.
+        let mut game = Game::with_size(4, 0);
+        let corners = [(0, 0), (0, 3), (3, 0), (3, 3)].map(|(i, j)| {
+            game.board = crate::game::Board::empty(4);
+            game.set(i, j, 5);
+            CornerSnake::default().evaluate(&game)
+        });
+        assert!(corners.iter().all(|&value| value == 32.0));
+    }
+
+    #[test]
+    fn weighted_sum() {
+        let mut game = from_rows([[1, 0], [0, 1]]);
+        game.score = 10;
+        assert_eq!(Weighted::new().evaluate(&game), 0.0);
+        let weighted = Weighted::new().add(2.0, Score).add(-1.0, EmptyCells);
+        assert_eq!(weighted.evaluate(&game), 18.0);
+    }
+}
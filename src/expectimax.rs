@@ -0,0 +1,153 @@
+use crate::evaluator::{EmptyCells, Evaluator, Score};
+use crate::game::{Actions, Game};
+
+use strum::IntoEnumIterator;
+
+/// The number of moves to look ahead.
+pub const DEPTH: usize = 3;
+/// Chance nodes that are reached with a lower probability are not expanded further.
+pub const MIN_PROBABILITY: f64 = 0.001;
+/// The value of an empty cell in the default evaluator, in points of score.
+const EMPTY_CELL_VALUE: f64 = 64.0;
+
+/// Scores a position by its score plus a bonus for every empty cell,
+/// and lost positions with 0.
+pub fn default_evaluator(game: &Game) -> f64 {
+    if game.is_game_over() {
+        return 0.0;
+    }
+    Score.evaluate(game) + EMPTY_CELL_VALUE * EmptyCells.evaluate(game)
+}
+
+/// A depth-limited expectimax search, which maximises over the player's
+/// moves and averages over the spawned tiles.
+/// Leaves are scored by the `evaluator`.
+pub struct Expectimax<E> {
+    pub depth: usize,
+    pub min_probability: f64,
+    pub evaluator: E,
+}
+
+impl Default for Expectimax<fn(&Game) -> f64> {
+    fn default() -> Self {
+        Self::new(default_evaluator)
+    }
+}
+
+impl<E: Evaluator> Expectimax<E> {
+    pub fn new(evaluator: E) -> Self {
+        Self {
+            depth: DEPTH,
+            min_probability: MIN_PROBABILITY,
+            evaluator,
+        }
+    }
+
+    /// Returns the expected value of every valid action.
+    pub fn action_values(&self, game: &Game) -> Vec<(Actions, f64)> {
+        Actions::iter()
+            .filter_map(|action| {
+                let afterstate = game.slide(action)?;
+                Some((action, self.chance_node(&afterstate, self.depth, 1.0)))
+            })
+            .collect()
+    }
+
+    /// Returns the action with the highest expected value, or `None` if the game is over.
+    pub fn best_move(&self, game: &Game) -> Option<Actions> {
+        self.action_values(game)
+            .into_iter()
+            .max_by(|(_, x), (_, y)| x.total_cmp(y))
+            .map(|(action, _)| action)
+    }
+
+    fn max_node(&self, game: &Game, depth: usize, probability: f64) -> f64 {
+        if depth == 0 {
+            return self.evaluator.evaluate(game);
+        }
+        Actions::iter()
+            .filter_map(|action| game.slide(action))
+            .map(|afterstate| self.chance_node(&afterstate, depth, probability))
+            .max_by(|x, y| x.total_cmp(y))
+            .unwrap_or_else(|| self.evaluator.evaluate(game))
+    }
+
+    fn chance_node(&self, afterstate: &Game, depth: usize, probability: f64) -> f64 {
+        if depth == 0 || probability < self.min_probability {
+            return self.evaluator.evaluate(afterstate);
+        }
+        afterstate
+            .spawn_outcomes()
+            .map(|(game, p)| p * self.max_node(&game, depth - 1, probability * p))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_move_when_game_over() {
+        let mut game = Game::with_size(2, 0);
+        game.board = crate::game::Board::empty(2);
+        game.set(0, 0, 1);
+        game.set(0, 1, 2);
+        game.set(1, 0, 2);
+        game.set(1, 1, 1);
+        assert_eq!(Expectimax::default().best_move(&game), None);
+    }
+
+    #[test]
+    fn takes_the_merge() {
+        let mut game = Game::with_size(4, 0);
+        game.board = crate::game::Board::empty(4);
+        game.set(0, 0, 10);
+        game.set(0, 3, 10);
+        let search = Expectimax {
+            depth: 1,
+            ..Expectimax::default()
+        };
+        let best = search.best_move(&game);
+        assert!(matches!(best, Some(Actions::Left) | Some(Actions::Right)));
+    }
+
+    #[test]
+    fn depth_zero_scores_the_afterstates() {
+        let game = Game::with_seed(1);
+        let search = Expectimax {
+            depth: 0,
+            ..Expectimax::default()
+        };
+        for (action, value) in search.action_values(&game) {
+            let afterstate = game.slide(action).unwrap();
+            assert_eq!(value, default_evaluator(&afterstate));
+        }
+        assert!(search.best_move(&game).is_some());
+    }
+
+    #[test]
+    fn custom_evaluator() {
+        let game = Game::with_seed(1);
+        let search = Expectimax {
+            depth: 1,
+            min_probability: 0.0,
+            evaluator: (|game: &Game| game.get(0, 0) as f64) as fn(&Game) -> f64,
+        };
+        let values = search.action_values(&game);
+        assert_eq!(values.len(), game.valid_moves().len());
+    }
+
+    #[test]
+    fn plays_better_than_random() {
+        let mut game = Game::with_seed(1);
+        let search = Expectimax {
+            depth: 1,
+            ..Expectimax::default()
+        };
+        while let Some(action) = search.best_move(&game) {
+            game.step(action);
+        }
+        assert!(game.highest_tile() >= Some(256));
+    }
+}
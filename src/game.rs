@@ -1,41 +1,385 @@
+use crate::rng::SplitMix64;
 use rand::seq::SliceRandom;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, convert::TryFrom};
+use std::{convert::TryFrom, sync::OnceLock};
 use strum::IntoEnumIterator;
 use strum_macros::{EnumCount as EnumCountMacro, EnumIter};
 
-const SIZE: usize = 4;
-const MAX_EXPONENT: usize = 17; // log2(131,072) is 17
+/// The board size of the classic game.
+pub const DEFAULT_SIZE: usize = 4;
+/// The largest supported board size, a row of 8 bit cells has to fit into a `u64`.
+pub const MAX_SIZE: usize = 8;
+pub const MAX_EXPONENT: usize = 17; // log2(131,072) is 17
+
+/// The largest exponent of a 4 bit cell. A board up to 4x4 is stored with 8 bit
+/// cells instead once a tile would pass 2^15, so every board plays by the same rules.
+pub const MAX_CELL: u32 = 15;
+/// Boards up to this size are packed into a single `u64` and slid using lookup tables.
+const MAX_SMALL_SIZE: usize = 4;
+const SMALL_CELL_BITS: usize = 4;
+const SMALL_ROW_BITS: usize = SMALL_CELL_BITS * MAX_SMALL_SIZE;
+const LARGE_CELL_BITS: usize = 8;
+/// The largest exponent of an 8 bit cell, tiles of which do not merge,
+/// far beyond any tile a game reaches.
+const MAX_LARGE_CELL: u32 = (1 << LARGE_CELL_BITS) - 1;
+/// The exponents of the spawned tiles and their probabilities.
+const SPAWN_PROBABILITIES: [(u32, f64); 2] = [(1, 0.9), (2, 0.1)];
+
+type Rows = [u64; MAX_SIZE];
+
+/// Lookup tables for sliding a single packed row of a small board, indexed by the row itself.
+struct MoveTables {
+    left: Vec<u16>,
+    right: Vec<u16>,
+    score_left: Vec<u32>,
+    score_right: Vec<u32>,
+}
 
-#[derive(Clone, Copy, PartialEq, Debug)]
-pub struct Tile {
-    pub idx: u32,
+fn move_tables(size: usize) -> &'static MoveTables {
+    static TABLES: [OnceLock<MoveTables>; MAX_SMALL_SIZE + 1] =
+        [const { OnceLock::new() }; MAX_SMALL_SIZE + 1];
+    TABLES[size].get_or_init(|| {
+        let n = 1 << (SMALL_CELL_BITS * size);
+        let mut tables = MoveTables {
+            left: Vec::with_capacity(n),
+            right: Vec::with_capacity(n),
+            score_left: Vec::with_capacity(n),
+            score_right: Vec::with_capacity(n),
+        };
+        for row in 0..n as u64 {
+            let (left, score_left) = slide_row(row, size, SMALL_CELL_BITS, false);
+            let (right, score_right) = slide_row(row, size, SMALL_CELL_BITS, true);
+            tables.left.push(left as u16);
+            tables.score_left.push(score_left);
+            tables.right.push(right as u16);
+            tables.score_right.push(score_right);
+        }
+        tables
+    })
+}
+
+/// Slides a row of `bits` bit cells towards column 0 and returns the new row and the score gained.
+fn slide_row_left(row: u64, size: usize, bits: usize) -> (u64, u32) {
+    let mask = (1 << bits) - 1;
+    let mut new_row = [0u64; MAX_SIZE];
+    let mut score = 0;
+    let mut pos = 0;
+    for value in (0..size)
+        .map(|j| (row >> (j * bits)) & mask)
+        .filter(|&value| value != 0)
+    {
+        if new_row[pos] == value && value < mask {
+            new_row[pos] += 1;
+            score += u32::pow(2, new_row[pos] as u32);
+            pos += 1;
+        } else if new_row[pos] == 0 {
+            new_row[pos] = value;
+        } else {
+            pos += 1;
+            new_row[pos] = value;
+        }
+    }
+    let packed = new_row
+        .iter()
+        .enumerate()
+        .fold(0, |acc, (j, &value)| acc | (value << (j * bits)));
+    (packed, score)
+}
+
+fn reverse_row(row: u64, size: usize, bits: usize) -> u64 {
+    let mask = (1 << bits) - 1;
+    (0..size).fold(0, |acc, j| {
+        let value = (row >> (j * bits)) & mask;
+        acc | (value << ((size - 1 - j) * bits))
+    })
+}
+
+/// Slides a row of `bits` bit cells towards column 0,
+/// or towards the last column if `reverse` is set.
+fn slide_row(row: u64, size: usize, bits: usize, reverse: bool) -> (u64, u32) {
+    if reverse {
+        let (new_row, score) = slide_row_left(reverse_row(row, size, bits), size, bits);
+        (reverse_row(new_row, size, bits), score)
+    } else {
+        slide_row_left(row, size, bits)
+    }
+}
+
+/// Returns whether a packed small board has a cell of [`MAX_CELL`],
+/// whose merge does not fit into 4 bits.
+fn has_max_cell(board: u64) -> bool {
+    board & (board >> 1) & (board >> 2) & (board >> 3) & 0x1111_1111_1111_1111 != 0
+}
+
+/// Transposes a packed 4x4 board, so that columns become rows.
+fn transpose_4x4(board: u64) -> u64 {
+    let a1 = board & 0xF0F0_0F0F_F0F0_0F0F;
+    let a2 = board & 0x0000_F0F0_0000_F0F0;
+    let a3 = board & 0x0F0F_0000_0F0F_0000;
+    let a = a1 | (a2 << 12) | (a3 >> 12);
+    let b1 = a & 0xFF00_FF00_00FF_00FF;
+    let b2 = a & 0x00FF_00FF_0000_0000;
+    let b3 = a & 0x0000_0000_FF00_FF00;
+    b1 | (b2 >> 24) | (b3 << 24)
+}
+
+/// The packed exponents of the cells of a board.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Board {
+    /// Boards up to 4x4 in a single `u64` with one 4 bit exponent per cell,
+    /// row `i` occupying bits `16 * i..16 * (i + 1)` and column `j` the
+    /// nibble `j` of that row.
+    Small(u64),
+    /// Larger boards, and smaller ones with a tile beyond 2^15, with a `u64`
+    /// per row and one 8 bit exponent per cell, column `j` occupying the byte `j` of its row.
+    Large(Rows),
+}
+
+impl Board {
+    /// Returns the empty board of `size` x `size` cells.
+    pub fn empty(size: usize) -> Self {
+        if size <= MAX_SMALL_SIZE {
+            Board::Small(0)
+        } else {
+            Board::Large([0; MAX_SIZE])
+        }
+    }
+
+    /// Returns the board with 8 bit cells, keeping large boards as they are.
+    fn widened(&self) -> Board {
+        match *self {
+            Board::Small(_) => {
+                let mut rows = [0; MAX_SIZE];
+                for (i, row) in rows.iter_mut().enumerate().take(MAX_SMALL_SIZE) {
+                    for j in 0..MAX_SMALL_SIZE {
+                        *row |= (self.get(i, j) as u64) << (j * LARGE_CELL_BITS);
+                    }
+                }
+                Board::Large(rows)
+            }
+            large => large,
+        }
+    }
+
+    /// Returns the board of `size` x `size` cells with 4 bit cells if all of them fit,
+    /// so that equal boards always have the same representation.
+    fn narrowed(&self, size: usize) -> Board {
+        match *self {
+            Board::Large(rows)
+                if size <= MAX_SMALL_SIZE
+                    && rows.iter().all(|row| row & 0xF0F0_F0F0_F0F0_F0F0 == 0) =>
+            {
+                let mut small = Board::empty(size);
+                for i in 0..size {
+                    for j in 0..size {
+                        small.set(i, j, self.get(i, j));
+                    }
+                }
+                small
+            }
+            board => board,
+        }
+    }
+
+    /// Returns the exponent of the tile at row `i` and column `j` (0 if empty).
+    pub fn get(&self, i: usize, j: usize) -> u32 {
+        match self {
+            Board::Small(board) => {
+                ((board >> (i * SMALL_ROW_BITS + j * SMALL_CELL_BITS)) & 0xF) as u32
+            }
+            Board::Large(rows) => ((rows[i] >> (j * LARGE_CELL_BITS)) & 0xFF) as u32,
+        }
+    }
+
+    /// Sets the exponent of the tile at row `i` and column `j`,
+    /// widening a small board to 8 bit cells if the tile does not fit into 4 bits.
+    pub fn set(&mut self, i: usize, j: usize, value: u32) {
+        assert!(value <= MAX_LARGE_CELL);
+        if value > MAX_CELL {
+            *self = self.widened();
+        }
+        match self {
+            Board::Small(board) => {
+                let shift = i * SMALL_ROW_BITS + j * SMALL_CELL_BITS;
+                *board = (*board & !(0xF << shift)) | ((value as u64) << shift);
+            }
+            Board::Large(rows) => {
+                let shift = j * LARGE_CELL_BITS;
+                rows[i] = (rows[i] & !(0xFF << shift)) | ((value as u64) << shift);
+            }
+        }
+    }
+
+    /// Slides every row towards column 0, or towards the last column if
+    /// `reverse` is set, and returns the new board and the score gained.
+    fn slide_rows(&self, size: usize, reverse: bool) -> (Board, u32) {
+        match self {
+            // Two tiles of 2^15 may merge into one that does not fit into 4 bits.
+            Board::Small(board) if has_max_cell(*board) => {
+                let (board, score) = self.widened().slide_rows(size, reverse);
+                (board.narrowed(size), score)
+            }
+            Board::Small(board) => {
+                let tables = move_tables(size);
+                let (moves, scores) = if reverse {
+                    (&tables.right, &tables.score_right)
+                } else {
+                    (&tables.left, &tables.score_left)
+                };
+                let (new_board, score) = (0..size).fold((0, 0), |(new_board, score), i| {
+                    let row = ((board >> (i * SMALL_ROW_BITS)) & 0xFFFF) as usize;
+                    (
+                        new_board | ((moves[row] as u64) << (i * SMALL_ROW_BITS)),
+                        score + scores[row],
+                    )
+                });
+                (Board::Small(new_board), score)
+            }
+            Board::Large(rows) => {
+                let mut new_rows = [0; MAX_SIZE];
+                let mut score = 0;
+                for (new_row, &row) in new_rows.iter_mut().zip(rows).take(size) {
+                    let (slid, gained) = slide_row(row, size, LARGE_CELL_BITS, reverse);
+                    *new_row = slid;
+                    score += gained;
+                }
+                (Board::Large(new_rows), score)
+            }
+        }
+    }
+
+    /// Transposes the board, so that columns become rows.
+    fn transpose(&self, size: usize) -> Board {
+        match *self {
+            Board::Small(board) if size == MAX_SMALL_SIZE => Board::Small(transpose_4x4(board)),
+            _ => {
+                let mut transposed = Board::empty(size);
+                for i in 0..size {
+                    for j in 0..size {
+                        transposed.set(j, i, self.get(i, j));
+                    }
+                }
+                transposed
+            }
+        }
+    }
+}
+
+/// Returns the cell at position `pos` of `line`, counted from the edge
+/// the tiles slide towards when playing `action`.
+fn line_cell(action: Actions, size: usize, line: usize, pos: usize) -> (usize, usize) {
+    match action {
+        Actions::Left => (line, pos),
+        Actions::Right => (line, size - 1 - pos),
+        Actions::Up => (pos, line),
+        Actions::Down => (size - 1 - pos, line),
+    }
+}
+
+/// A tile that was spawned on the board, `value` is its exponent.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Spawn {
+    pub row: usize,
+    pub col: usize,
+    pub value: u32,
+}
+
+/// A tile that slid from one cell to another (or stayed in place).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TileMove {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+}
+
+/// Two tiles that merged in a cell, `value` is the exponent of the resulting tile.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Merge {
+    pub at: (usize, usize),
     pub value: u32,
-    new: bool,
-    changed: bool,
 }
 
-impl Tile {
-    pub fn new(idx: u32, value: u32) -> Self {
-        Tile {
-            idx,
-            value,
-            new: true,
-            changed: false,
+/// The result of a move: whether the board changed, the score gained and the spawned tile.
+///
+/// The tile movements and merges are only computed when asked for, so that
+/// the outcome stays cheap for callers that only need the score.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MoveOutcome {
+    pub action: Actions,
+    pub changed: bool,
+    pub score: u32,
+    pub spawn: Option<Spawn>,
+    size: usize,
+    before: Board,
+}
+
+impl MoveOutcome {
+    /// Returns where every tile of the board before the move ended up.
+    /// Of two merged tiles, the one closer to the edge comes first.
+    pub fn movements(&self) -> Vec<TileMove> {
+        self.slide_details().0
+    }
+
+    /// Returns all merges of the move.
+    pub fn merges(&self) -> Vec<Merge> {
+        self.slide_details().1
+    }
+
+    fn slide_details(&self) -> (Vec<TileMove>, Vec<Merge>) {
+        let mut movements = vec![];
+        let mut merges = vec![];
+        for line in 0..self.size {
+            // Position, value and whether it was merged for the last tile placed in the line.
+            let mut last: Option<(usize, u32, bool)> = None;
+            for pos in 0..self.size {
+                let from = line_cell(self.action, self.size, line, pos);
+                let value = self.before.get(from.0, from.1);
+                if value == 0 {
+                    continue;
+                }
+                let to = match last {
+                    Some((to, last_value, false))
+                        if last_value == value && value < MAX_LARGE_CELL =>
+                    {
+                        last = Some((to, value + 1, true));
+                        let at = line_cell(self.action, self.size, line, to);
+                        merges.push(Merge {
+                            at,
+                            value: value + 1,
+                        });
+                        to
+                    }
+                    _ => {
+                        let to = last.map_or(0, |(to, _, _)| to + 1);
+                        last = Some((to, value, false));
+                        to
+                    }
+                };
+                movements.push(TileMove {
+                    from,
+                    to: line_cell(self.action, self.size, line, to),
+                });
+            }
         }
+        (movements, merges)
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+/// A 2048 game on a square board of `size` x `size` cells, packed into a [`Board`].
+///
+/// The game owns its random number generator, so a seed
+/// fully determines the spawned tiles.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Game {
-    pub board: [[Tile; SIZE]; SIZE],
+    pub board: Board,
     pub score: u32,
     pub moves: usize,
-    pub tiles: u32,
+    size: usize,
+    seed: u64,
+    rng: SplitMix64,
 }
 
-#[derive(Debug, Copy, Clone, EnumIter, EnumCountMacro, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, EnumIter, EnumCountMacro, Serialize, Deserialize)]
 pub enum Actions {
     Left,
     Right,
@@ -64,44 +408,57 @@ impl Default for Game {
 
 impl Game {
     pub fn new() -> Self {
+        Self::with_seed(rand::thread_rng().gen())
+    }
+
+    /// Creates a new game whose tiles are determined by `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::with_size(DEFAULT_SIZE, seed)
+    }
+
+    /// Creates a new game on a `size` x `size` board, whose tiles are determined by `seed`.
+    pub fn with_size(size: usize, seed: u64) -> Self {
+        assert!((2..=MAX_SIZE).contains(&size), "Unsupported board size");
         let mut game = Game {
-            board: [[Tile::new(0, 0); SIZE]; SIZE],
+            board: Board::empty(size),
             score: 0,
             moves: 0,
-            tiles: 0,
+            size,
+            seed,
+            rng: SplitMix64::seed_from_u64(seed),
         };
         game.add_tile();
         game.add_tile();
         game
     }
 
+    /// The number of rows (and columns) of the board.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The seed this game was started with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the exponent of the tile at row `i` and column `j` (0 if empty).
+    pub fn get(&self, i: usize, j: usize) -> u32 {
+        self.board.get(i, j)
+    }
+
+    /// Sets the exponent of the tile at row `i` and column `j`.
+    pub fn set(&mut self, i: usize, j: usize, value: u32) {
+        self.board.set(i, j, value)
+    }
+
+    /// Iterates over the exponents of all cells in row-major order.
+    pub fn cells(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.size * self.size).map(|k| self.get(k / self.size, k % self.size))
+    }
+
     pub fn highest_tile(&self) -> Option<u32> {
-        Some(u32::pow(
-            2,
-            self.board
-                .iter()
-                .flat_map(|row| row.iter().map(|tile| tile.value))
-                .max()?,
-        ))
-    }
-
-    /// Return the hashmap of all tiles
-    /// index is a unique id, then i, j and value
-    pub fn tiles(&self) -> BTreeMap<u32, (usize, usize, u32, bool, bool)> {
-        let mut hm: BTreeMap<u32, (usize, usize, u32, bool, bool)> = BTreeMap::new();
-
-        self.board.iter().enumerate().for_each(|(i, row)| {
-            row.iter()
-                .enumerate()
-                .filter(|&(_, &tile)| tile.value > 0)
-                .for_each(|(j, tile)| {
-                    hm.insert(
-                        tile.idx,
-                        (i, j, u32::pow(2, tile.value), tile.new, tile.changed),
-                    );
-                })
-        });
-        hm
+        Some(u32::pow(2, self.cells().max()?))
     }
 
     pub fn valid_moves(&self) -> Vec<Actions> {
@@ -109,14 +466,7 @@ impl Game {
         for action in Actions::iter() {
             let mut current_game = *self;
 
-            let changed = match action {
-                Actions::Left => current_game.move_left(),
-                Actions::Right => current_game.move_right(),
-                Actions::Up => current_game.move_up(),
-                Actions::Down => current_game.move_down(),
-            };
-
-            if changed {
+            if current_game.make_move(action).changed {
                 moves.push(action)
             }
         }
@@ -124,127 +474,144 @@ impl Game {
     }
 
     pub fn empty_tiles(&self) -> Vec<(usize, usize)> {
-        let empty_tiles: Vec<(usize, usize)> = self
-            .board
-            .iter()
+        self.cells()
             .enumerate()
-            .flat_map(|(i, row)| {
-                row.iter()
-                    .enumerate()
-                    .filter(|&(_, &tile)| tile.value == 0)
-                    .map(move |(j, _)| (i, j))
-            })
-            .collect();
-        empty_tiles
-    }
-
-    pub fn add_tile(&mut self) {
-        let mut rng = rand::thread_rng();
+            .filter(|&(_, value)| value == 0)
+            .map(|(k, _)| (k / self.size, k % self.size))
+            .collect()
+    }
+
+    /// Adds a random tile using the game's own random number generator.
+    pub fn add_tile(&mut self) -> Option<Spawn> {
+        let mut rng = self.rng;
+        let spawn = self.add_tile_with(&mut rng);
+        self.rng = rng;
+        spawn
+    }
+
+    /// Adds a random tile using `rng`, leaving the game's own generator untouched.
+    pub fn add_tile_with<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Option<Spawn> {
         let empty_tiles = self.empty_tiles();
-        self.tiles += 1;
 
-        if let Some(&(i, j)) = empty_tiles.choose(&mut rng) {
-            let value = if rng.gen_range(0..10) == 0 { 2 } else { 1 };
-            self.board[i][j] = Tile::new(self.tiles, value);
+        let &(row, col) = empty_tiles.choose(rng)?;
+        let value = if rng.gen_range(0..10) == 0 { 2 } else { 1 };
+        self.set(row, col, value);
+        Some(Spawn { row, col, value })
+    }
+
+    pub fn step(&mut self, action: Actions) -> MoveOutcome {
+        let mut rng = self.rng;
+        let outcome = self.step_with(action, &mut rng);
+        self.rng = rng;
+        outcome
+    }
+
+    /// Performs a step, spawning the new tile using `rng`.
+    pub fn step_with<R: Rng + ?Sized>(&mut self, action: Actions, rng: &mut R) -> MoveOutcome {
+        let mut outcome = self.make_move(action);
+
+        if outcome.changed {
+            outcome.spawn = self.add_tile_with(rng);
+            self.moves += 1;
         }
+        outcome
+    }
+
+    /// Replaces the board by `board` and adds `score`,
+    /// returning whether the board changed.
+    fn apply(&mut self, board: Board, score: u32) -> bool {
+        let changed = board != self.board;
+        self.board = board;
+        self.score += score;
+        changed
+    }
+
+    /// Returns the afterstate of `action`: the game after sliding the tiles
+    /// and before a new tile is spawned, or `None` if the board does not change.
+    ///
+    /// The move is already counted, so spawning any of
+    /// `spawn_outcomes` on it gives the same game as `step`.
+    pub fn slide(&self, action: Actions) -> Option<Game> {
+        let mut afterstate = *self;
+        if afterstate.make_move(action).changed {
+            afterstate.moves += 1;
+            Some(afterstate)
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over every game that can result from spawning a tile,
+    /// together with its probability.
+    pub fn spawn_outcomes(&self) -> impl Iterator<Item = (Game, f64)> {
+        let game = *self;
+        let empty_tiles = self.empty_tiles();
+        let n = empty_tiles.len() as f64;
+        empty_tiles.into_iter().flat_map(move |(i, j)| {
+            SPAWN_PROBABILITIES
+                .iter()
+                .map(move |&(value, probability)| {
+                    let mut outcome = game;
+                    outcome.set(i, j, value);
+                    (outcome, probability / n)
+                })
+        })
     }
 
-    pub fn step(&mut self, action: Actions) -> bool {
+    /// Slides the tiles in the direction of `action` without adding a new tile.
+    pub fn make_move(&mut self, action: Actions) -> MoveOutcome {
+        let before = self.board;
+        let score = self.score;
         let changed = match action {
             Actions::Left => self.move_left(),
             Actions::Right => self.move_right(),
             Actions::Up => self.move_up(),
             Actions::Down => self.move_down(),
         };
-
-        if changed {
-            self.add_tile();
-            self.moves += 1;
+        MoveOutcome {
+            action,
+            changed,
+            score: self.score - score,
+            spawn: None,
+            size: self.size,
+            before,
         }
-        changed
     }
 
     pub fn move_left(&mut self) -> bool {
-        let mut changed = false;
-        for row in &mut self.board {
-            let mut new_row = [Tile::new(0, 0); SIZE];
-            let mut pos = 0;
-            for &tile in row.iter().filter(|&&tile| tile.value != 0) {
-                if new_row[pos].value == tile.value {
-                    new_row[pos].value += 1;
-                    new_row[pos].changed = true;
-                    self.score += u32::pow(2, new_row[pos].value);
-                    pos += 1;
-                } else if new_row[pos].value == 0 {
-                    new_row[pos] = tile;
-                    new_row[pos].changed = false;
-                } else {
-                    pos += 1;
-                    new_row[pos] = tile;
-                    new_row[pos].changed = false;
-                }
-                new_row[pos].new = false;
-            }
-            if new_row.iter().map(|t| t.value).collect::<Vec<u32>>()
-                != *row.iter().map(|t| t.value).collect::<Vec<u32>>()
-            {
-                changed = true
-            }
-            *row = new_row;
-        }
-        changed
+        let (board, score) = self.board.slide_rows(self.size, false);
+        self.apply(board, score)
     }
 
     pub fn move_right(&mut self) -> bool {
-        for row in &mut self.board {
-            row.reverse();
-        }
-        let changed = self.move_left();
-        for row in &mut self.board {
-            row.reverse();
-        }
-        changed
+        let (board, score) = self.board.slide_rows(self.size, true);
+        self.apply(board, score)
     }
 
     pub fn move_up(&mut self) -> bool {
-        self.transpose();
-        let changed = self.move_left();
-        self.transpose();
-        changed
+        let columns = self.board.transpose(self.size);
+        let (board, score) = columns.slide_rows(self.size, false);
+        self.apply(board.transpose(self.size), score)
     }
 
     pub fn move_down(&mut self) -> bool {
-        self.transpose();
-        let changed = self.move_right();
-        self.transpose();
-        changed
-    }
-
-    fn transpose(&mut self) {
-        let new_board: [[Tile; SIZE]; SIZE] = (0..SIZE)
-            .map(|i| {
-                (0..SIZE)
-                    .map(|j| self.board[j][i])
-                    .collect::<Vec<_>>()
-                    .try_into()
-                    .unwrap() // Convert Vec to array
-            })
-            .collect::<Vec<_>>()
-            .try_into()
-            .unwrap(); // Convert Vec to array
-        self.board = new_board;
+        let columns = self.board.transpose(self.size);
+        let (board, score) = columns.slide_rows(self.size, true);
+        self.apply(board.transpose(self.size), score)
     }
 
     pub fn is_game_over(&self) -> bool {
-        for i in 0..SIZE {
-            for j in 0..SIZE {
-                if self.board[i][j].value == 0 {
+        let size = self.size;
+        for i in 0..size {
+            for j in 0..size {
+                let value = self.get(i, j);
+                if value == 0 {
                     return false;
                 }
-                if i < SIZE - 1 && self.board[i][j].value == self.board[i + 1][j].value {
+                if i < size - 1 && value < MAX_LARGE_CELL && value == self.get(i + 1, j) {
                     return false;
                 }
-                if j < SIZE - 1 && self.board[i][j].value == self.board[i][j + 1].value {
+                if j < size - 1 && value < MAX_LARGE_CELL && value == self.get(i, j + 1) {
                     return false;
                 }
             }
@@ -253,18 +620,15 @@ impl Game {
     }
 
     pub fn flatten(&self) -> Vec<f64> {
-        let res: Vec<f64> = self
-            .board
-            .into_iter()
-            .flat_map(|row| row.into_iter().map(|tile| tile.value as f64))
-            .collect();
-        res
+        self.cells().map(|value| value as f64).collect()
     }
 
+    /// Encodes every cell as `MAX_EXPONENT` flags, where tiles beyond the
+    /// flags set the last one.
     pub fn one_hot_encode_board(&self) -> Vec<f64> {
-        let mut encoded = vec![0.0; SIZE * SIZE * MAX_EXPONENT];
-        for (i, &tile) in self.board.iter().flatten().enumerate() {
-            let tile = tile.value as usize;
+        let mut encoded = vec![0.0; self.size * self.size * MAX_EXPONENT];
+        for (i, tile) in self.cells().enumerate() {
+            let tile = (tile as usize).min(MAX_EXPONENT - 1);
             if tile != 0 {
                 encoded[i * MAX_EXPONENT + tile] = 1.0;
             }
@@ -272,82 +636,307 @@ impl Game {
         encoded
     }
 
+    /// Starts a new game of the same size, seeded from the game's own generator.
     pub fn reset(&mut self) -> Vec<f64> {
-        self.board = [[Tile::new(0, 0); SIZE]; SIZE];
-        self.score = 0;
-        self.moves = 0;
-        self.tiles = 0;
-        self.add_tile();
-        self.add_tile();
+        *self = Game::with_size(self.size, self.rng.gen());
         self.flatten()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::game::{MAX_EXPONENT, SIZE};
+    use crate::game::{DEFAULT_SIZE as SIZE, MAX_EXPONENT};
 
-    use super::Game;
-    use super::Tile;
+    use super::{move_tables, slide_row, Actions, Board, Game, Merge, TileMove};
 
     fn test_game() -> Game {
         let mut game = Game::new();
-        game.board = [
-            [
-                Tile::new(0, 0),
-                Tile::new(1, 1),
-                Tile::new(2, 2),
-                Tile::new(3, 3),
-            ],
-            [
-                Tile::new(4, 4),
-                Tile::new(5, 5),
-                Tile::new(6, 6),
-                Tile::new(7, 7),
-            ],
-            [
-                Tile::new(8, 8),
-                Tile::new(9, 9),
-                Tile::new(10, 10),
-                Tile::new(11, 11),
-            ],
-            [
-                Tile::new(12, 12),
-                Tile::new(13, 13),
-                Tile::new(14, 14),
-                Tile::new(15, 15),
-            ],
-        ];
+        for i in 0..SIZE {
+            for j in 0..SIZE {
+                game.set(i, j, (i * SIZE + j) as u32);
+            }
+        }
+        game
+    }
+
+    fn from_rows<const N: usize>(rows: [[u32; N]; N]) -> Game {
+        let mut game = Game::with_size(N, 0);
+        for (i, row) in rows.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                game.set(i, j, value);
+            }
+        }
         game
     }
 
     #[test]
     fn highest_tile() {
         let game = test_game();
-        assert_eq!(game.highest_tile(), Some(32768 as u32));
+        assert_eq!(game.highest_tile(), Some(32768));
     }
 
     #[test]
     fn game_over() {
         let mut game = test_game();
-        game.board[0][0].value = 16;
-        assert_eq!(game.is_game_over(), true);
+        game.set(0, 0, 16);
+        assert!(game.is_game_over());
     }
 
     #[test]
     fn invalid_move() {
         let mut game = test_game();
-        assert_eq!(game.move_down(), false);
-        assert_eq!(game.move_right(), false);
+        assert!(!game.move_down());
+        assert!(!game.move_right());
     }
 
     #[test]
     fn merge_cells() {
         let mut game = test_game();
 
-        game.board[3][2].value = 15;
-        assert_eq!(game.move_right(), true);
-        assert_eq!(game.highest_tile(), Some(65536 as u32));
+        game.set(3, 2, 15);
+        assert!(game.move_right());
+        assert_eq!(game.highest_tile(), Some(65536));
+    }
+
+    #[test]
+    fn small_boards_widen_beyond_the_small_cells() {
+        let mut game = from_rows([[15, 15, 14], [0, 0, 0], [0, 0, 0]]);
+        let unchanged = game;
+        assert!(!game.move_up());
+        assert_eq!(game, unchanged);
+
+        assert!(game.move_left());
+        assert_eq!(game.get(0, 0), 16);
+        assert_eq!(game.get(0, 1), 14);
+        assert_eq!(game.score, 65536);
+        assert!(game.move_down());
+        assert_eq!(game.get(2, 0), 16);
+        assert_eq!(game.get(2, 1), 14);
+        assert!(!game.is_game_over());
+    }
+
+    #[test]
+    fn large_boards_merge_beyond_the_small_cells() {
+        let mut game = Game::with_size(5, 0);
+        game.board = Board::empty(5);
+        game.set(2, 1, 15);
+        game.set(4, 1, 15);
+        assert!(game.move_up());
+        assert_eq!(game.get(0, 1), 16);
+        assert_eq!(game.score, 65536);
+        assert_eq!(game.highest_tile(), Some(65536));
+    }
+
+    #[test]
+    fn transposes_match_for_every_size() {
+        for size in 2..=super::MAX_SIZE {
+            let mut game = Game::with_size(size, 0);
+            for k in 0..size * size {
+                game.set(k / size, k % size, (k % 15) as u32 + 1);
+            }
+            let transposed = game.board.transpose(size);
+            for i in 0..size {
+                for j in 0..size {
+                    assert_eq!(transposed.get(j, i), game.get(i, j));
+                }
+            }
+            assert_eq!(transposed.transpose(size), game.board);
+        }
+    }
+
+    #[test]
+    fn moves_in_all_directions() {
+        let rows = [[1, 1, 2, 0], [0, 0, 0, 0], [1, 0, 2, 2], [0, 0, 0, 0]];
+
+        let mut game = from_rows(rows);
+        assert!(game.move_left());
+        assert_eq!(game.score, 4 + 8);
+        assert_eq!(
+            game.board,
+            from_rows([[2, 2, 0, 0], [0, 0, 0, 0], [1, 3, 0, 0], [0, 0, 0, 0]]).board
+        );
+
+        let mut game = from_rows(rows);
+        assert!(game.move_right());
+        assert_eq!(game.score, 4 + 8);
+        assert_eq!(
+            game.board,
+            from_rows([[0, 0, 2, 2], [0, 0, 0, 0], [0, 0, 1, 3], [0, 0, 0, 0]]).board
+        );
+
+        let mut game = from_rows(rows);
+        assert!(game.move_up());
+        assert_eq!(game.score, 4 + 8);
+        assert_eq!(
+            game.board,
+            from_rows([[2, 1, 3, 2], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0]]).board
+        );
+
+        let mut game = from_rows(rows);
+        assert!(game.move_down());
+        assert_eq!(game.score, 4 + 8);
+        assert_eq!(
+            game.board,
+            from_rows([[0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 0], [2, 1, 3, 2]]).board
+        );
+    }
+
+    #[test]
+    fn other_board_sizes() {
+        let mut game = from_rows([[1, 1, 0], [0, 2, 2], [1, 0, 1]]);
+        assert!(game.move_left());
+        assert_eq!(
+            game.board,
+            from_rows([[2, 0, 0], [3, 0, 0], [2, 0, 0]]).board
+        );
+        assert_eq!(game.score, 4 + 8 + 4);
+        assert_eq!(game.flatten().len(), 9);
+        assert_eq!(game.one_hot_encode_board().len(), 9 * MAX_EXPONENT);
+
+        let mut game = from_rows([
+            [1, 1, 1, 1, 2, 2],
+            [0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 1],
+            [0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0],
+        ]);
+        assert!(game.move_right());
+        assert_eq!(game.score, 8 + 4 + 4);
+        assert!(game.move_down());
+        let mut expected = [[0; 6]; 6];
+        expected[4] = [0, 0, 0, 0, 0, 3];
+        expected[5] = [0, 0, 0, 2, 2, 1];
+        assert_eq!(game.board, from_rows(expected).board);
+        assert_eq!(game.empty_tiles().len(), 36 - 4);
+        assert!(!game.is_game_over());
+    }
+
+    #[test]
+    fn tables_match_direct_slides() {
+        for size in 2..=SIZE {
+            let tables = move_tables(size);
+            for row in 0..1u32 << (4 * size) {
+                let (left, score_left) = slide_row(row as u64, size, 4, false);
+                let (right, score_right) = slide_row(row as u64, size, 4, true);
+                assert_eq!(tables.left[row as usize] as u64, left);
+                assert_eq!(tables.score_left[row as usize], score_left);
+                assert_eq!(tables.right[row as usize] as u64, right);
+                assert_eq!(tables.score_right[row as usize], score_right);
+            }
+        }
+    }
+
+    #[test]
+    fn move_outcome() {
+        let mut game = from_rows([[1, 1, 2, 0], [0, 0, 0, 0], [0, 0, 0, 0], [0, 0, 0, 1]]);
+        let outcome = game.step(Actions::Left);
+        assert!(outcome.changed);
+        assert_eq!(outcome.score, 4);
+        let spawn = outcome.spawn.expect("A tile should have been spawned");
+        assert_eq!(game.get(spawn.row, spawn.col), spawn.value);
+        assert_eq!(
+            outcome.movements(),
+            vec![
+                TileMove {
+                    from: (0, 0),
+                    to: (0, 0)
+                },
+                TileMove {
+                    from: (0, 1),
+                    to: (0, 0)
+                },
+                TileMove {
+                    from: (0, 2),
+                    to: (0, 1)
+                },
+                TileMove {
+                    from: (3, 3),
+                    to: (3, 0)
+                },
+            ]
+        );
+        assert_eq!(
+            outcome.merges(),
+            vec![Merge {
+                at: (0, 0),
+                value: 2
+            }]
+        );
+
+        let mut game = test_game();
+        let outcome = game.step(Actions::Right);
+        assert!(!outcome.changed);
+        assert_eq!(outcome.spawn, None);
+        assert_eq!(outcome.score, 0);
+        assert_eq!(game.moves, 0);
+    }
+
+    #[test]
+    fn movements_match_the_board() {
+        let mut game = Game::with_size(5, 11);
+        while !game.is_game_over() {
+            let before = game;
+            let action = game.valid_moves()[game.moves % game.valid_moves().len()];
+            let outcome = game.step(action);
+
+            let mut expected = Game::with_size(5, 0);
+            expected.board = Board::empty(5);
+            for TileMove { from, to } in outcome.movements() {
+                expected.set(to.0, to.1, before.get(from.0, from.1));
+            }
+            for Merge { at, value } in outcome.merges() {
+                expected.set(at.0, at.1, value);
+            }
+            let spawn = outcome.spawn.expect("A tile should have been spawned");
+            expected.set(spawn.row, spawn.col, spawn.value);
+            assert_eq!(expected.board, game.board);
+        }
+    }
+
+    #[test]
+    fn afterstates_and_spawns() {
+        let game = test_game();
+        assert_eq!(game.slide(Actions::Right), None);
+
+        let game = from_rows([[1, 1, 0, 0], [0; 4], [0; 4], [0; 4]]);
+        let afterstate = game.slide(Actions::Left).expect("The board should change");
+        assert_eq!(afterstate.get(0, 0), 2);
+        assert_eq!(afterstate.empty_tiles().len(), 15);
+        assert_eq!(afterstate.moves, 1);
+        assert_eq!(afterstate.score, 4);
+
+        let outcomes: Vec<(Game, f64)> = afterstate.spawn_outcomes().collect();
+        assert_eq!(outcomes.len(), 30);
+        let total: f64 = outcomes.iter().map(|(_, p)| p).sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(outcomes
+            .iter()
+            .all(|(outcome, _)| outcome.empty_tiles().len() == 14));
+
+        let mut stepped = game;
+        stepped.step(Actions::Left);
+        assert!(outcomes
+            .iter()
+            .any(|(outcome, _)| outcome.board == stepped.board
+                && outcome.score == stepped.score
+                && outcome.moves == stepped.moves));
+    }
+
+    #[test]
+    fn seeded_games() {
+        let play = |seed| {
+            let mut game = Game::with_seed(seed);
+            let mut boards = vec![game.board];
+            while !game.is_game_over() && game.moves < 50 {
+                let action = game.valid_moves()[0];
+                game.step(action);
+                boards.push(game.board);
+            }
+            boards
+        };
+        assert_eq!(play(7), play(7));
+        assert_ne!(play(7), play(8));
     }
 
     #[test]
@@ -355,10 +944,7 @@ mod tests {
         let game = test_game();
         let flat = game.flatten();
         assert_eq!(flat.len(), 16);
-        assert_eq!(
-            flat,
-            (0..16).into_iter().map(|x| x as f64).collect::<Vec<f64>>()
-        )
+        assert_eq!(flat, (0..16).map(|x| x as f64).collect::<Vec<f64>>())
     }
 
     #[test]
@@ -0,0 +1,131 @@
+use crate::game::{Actions, Game, MoveOutcome};
+use std::collections::VecDeque;
+
+/// A game together with the states that led to it, so that moves can be undone and redone.
+///
+/// Every recorded state is a full `Game`, including the spawned tiles
+/// and the state of its random number generator. Redoing a move,
+/// or playing the same move again after an undo, therefore spawns the same tile.
+#[derive(Clone, Debug)]
+pub struct GameHistory {
+    past: VecDeque<Game>,
+    current: Game,
+    future: Vec<Game>,
+    depth: usize,
+}
+
+impl GameHistory {
+    /// Creates a new history starting at `game`, remembering at most `depth` moves.
+    pub fn new(game: Game, depth: usize) -> Self {
+        Self {
+            past: VecDeque::new(),
+            current: game,
+            future: vec![],
+            depth,
+        }
+    }
+
+    /// Returns the current state of the game.
+    pub fn game(&self) -> &Game {
+        &self.current
+    }
+
+    /// Performs a step on the current game and records the previous state.
+    /// A move that changes the board discards all states that could be redone.
+    pub fn step(&mut self, action: Actions) -> MoveOutcome {
+        let previous = self.current;
+        let outcome = self.current.step(action);
+        if outcome.changed {
+            self.past.push_back(previous);
+            if self.past.len() > self.depth {
+                self.past.pop_front();
+            }
+            self.future.clear();
+        }
+        outcome
+    }
+
+    /// Goes back to the state before the last move, returns `false` if there is none.
+    pub fn undo(&mut self) -> bool {
+        match self.past.pop_back() {
+            Some(previous) => {
+                self.future.push(self.current);
+                self.current = previous;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replays the last undone move, returns `false` if there is none.
+    pub fn redo(&mut self) -> bool {
+        match self.future.pop() {
+            Some(next) => {
+                self.past.push_back(self.current);
+                self.current = next;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.past.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.future.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(history: &mut GameHistory, moves: usize) {
+        for _ in 0..moves {
+            let action = history.game().valid_moves()[0];
+            history.step(action);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo() {
+        let mut history = GameHistory::new(Game::with_seed(1), 10);
+        let start = *history.game();
+        play(&mut history, 3);
+        let end = *history.game();
+
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(!history.undo());
+        assert_eq!(*history.game(), start);
+
+        assert!(history.redo());
+        assert!(history.redo());
+        assert!(history.redo());
+        assert!(!history.redo());
+        assert_eq!(*history.game(), end);
+    }
+
+    #[test]
+    fn step_clears_redo() {
+        let mut history = GameHistory::new(Game::with_seed(2), 10);
+        play(&mut history, 2);
+        history.undo();
+        assert!(history.can_redo());
+        play(&mut history, 1);
+        assert!(!history.can_redo());
+    }
+
+    #[test]
+    fn limited_depth() {
+        let mut history = GameHistory::new(Game::with_seed(3), 2);
+        play(&mut history, 5);
+        assert!(history.undo());
+        assert!(history.undo());
+        assert!(!history.can_undo());
+        assert_eq!(history.game().moves, 3);
+    }
+}
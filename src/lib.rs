@@ -1,5 +1,15 @@
+pub mod bench;
+pub mod evaluator;
+pub mod expectimax;
 pub mod game;
+pub mod history;
 pub mod mcts;
 pub mod nn;
+pub mod ntuple;
+pub mod policy;
 pub mod population;
+pub mod replay;
+pub mod rng;
+pub mod search;
+pub mod time;
 pub mod ui;
@@ -1,8 +1,24 @@
-use clap::{Parser, Subcommand};
+use bench::BenchStats;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use evaluator::{CornerSnake, EmptyCells, Evaluator, Monotonicity, Score, Smoothness, Weighted};
+use game::{Game, DEFAULT_SIZE, MAX_SIZE};
+use itertools::Itertools;
 use leptos::*;
 use leptos_2048::*;
-use nn::{activation::ActivationFunction, NeuralNetwork};
+use nn::{binary::Precision, model::Metadata, NeuralNetwork};
+use ntuple::NTupleNetwork;
+use policy::{Policy, Strategy};
+use population::checkpoint::Checkpoint;
+use population::config::{TrainConfig, TrainOverrides, MAX_SEED};
 use population::Population;
+use rand::{Rng, SeedableRng};
+use replay::Replay;
+use rng::SplitMix64;
+use search::SearchConfig;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
 use ui::RenderGame;
 
 /// Wordle solver
@@ -25,12 +41,212 @@ enum Commands {
         /// Load the model from file
         #[arg(short, long)]
         load: Option<String>,
+
+        /// The training settings, from a TOML file if the name ends with .toml
+        /// and from a JSON file otherwise
+        #[arg(short, long)]
+        config: Option<String>,
+
+        /// Save the whole population every 10 steps, to resume the training from,
+        /// and its settings next to it
+        #[arg(long)]
+        checkpoint: Option<String>,
+
+        /// Resume the training from a checkpoint, with its settings
+        #[arg(long, conflicts_with_all = [
+            "load", "config", "agents", "seed", "size", "encoding", "layer_sizes",
+            "activation_functions",
+        ])]
+        resume: Option<String>,
+
+        #[command(flatten)]
+        overrides: Box<TrainOverrides>,
+    },
+
+    /// Validate a replay by re-simulating it
+    Replay {
+        /// The replay file
+        file: String,
+    },
+
+    /// Play a single game with a search or a model
+    Play {
+        /// Seed for a reproducible game
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Number of rows and columns of the board
+        #[arg(long, default_value_t = DEFAULT_SIZE, value_parser = board_size)]
+        size: usize,
+
+        /// Save the replay of the game
+        #[arg(short, long)]
+        replay: Option<String>,
+
+        #[command(flatten)]
+        policy: PolicyArgs,
+    },
+
+    /// Train an n-tuple network with temporal-difference learning
+    Ntuple {
+        /// Number of games to learn from
+        #[arg(short, long, default_value_t = 100_000)]
+        episodes: usize,
+
+        /// Step size of the updates
+        #[arg(long, default_value_t = ntuple::LEARNING_RATE)]
+        learning_rate: f32,
+
+        /// Save the network every 1000 episodes
+        #[arg(short, long)]
+        save: Option<String>,
+
+        /// Load the network from file
+        #[arg(short, long)]
+        load: Option<String>,
+
+        /// Seed for a reproducible training run
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Number of rows and columns of the board
+        #[arg(long, default_value_t = DEFAULT_SIZE, value_parser = board_size)]
+        size: usize,
+
+        /// The tuples as row-major cell indices, e.g. "0,1,2,3;0,1,4,5"
+        #[arg(long)]
+        patterns: Option<String>,
+    },
+
+    /// Convert a model between the JSON and the binary format
+    Convert {
+        /// The model to convert, in either format
+        input: String,
+
+        /// Where to save the model, in JSON if the name ends with .json
+        /// and in the binary format otherwise
+        output: String,
+
+        /// The precision of the weights in the binary format
+        #[arg(long, value_enum, default_value_t = Precision::F32)]
+        precision: Precision,
+    },
+
+    /// Play many games in parallel and report statistics
+    Bench {
+        /// Number of games to play
+        #[arg(short = 'n', long, default_value_t = 100)]
+        games: usize,
+
+        /// Seed for reproducible games
+        #[arg(long)]
+        seed: Option<u64>,
+
+        /// Number of rows and columns of the board
+        #[arg(long, default_value_t = DEFAULT_SIZE, value_parser = board_size)]
+        size: usize,
+
+        /// How to print the statistics
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+        format: OutputFormat,
+
+        #[command(flatten)]
+        policy: PolicyArgs,
     },
 }
 
-pub const BRAIN_MUTATION_RATE: f64 = 0.1;
-pub const BRAIN_MUTATION_VARIATION: f64 = 0.1;
-pub const AGENTS_KEEP_PROPORTION: f64 = 0.02;
+/// The policy that plays the games.
+#[derive(Args, Debug)]
+struct PolicyArgs {
+    /// The strategy used to choose the moves
+    #[arg(short, long, value_enum, default_value_t = Strategy::Expectimax)]
+    player: Strategy,
+
+    /// Play with the model from this file instead of the player
+    #[arg(short, long)]
+    model: Option<String>,
+
+    /// How the search scores positions, the default depends on the player
+    #[arg(short, long, value_enum)]
+    evaluator: Option<Evaluators>,
+
+    /// Score positions with the n-tuple network from this file
+    #[arg(long, conflicts_with = "evaluator")]
+    ntuple: Option<String>,
+
+    #[command(flatten)]
+    search: SearchConfig,
+}
+
+impl PolicyArgs {
+    /// Returns a function that builds the policy of a game on a board of `size` from its seed.
+    fn factory(&self, size: usize) -> impl Fn(u64) -> Box<dyn Policy> + Sync {
+        let nn = self.model.as_ref().map(|file| {
+            let nn = NeuralNetwork::load(file).expect("Failed to load NN");
+            assert!(nn.supports(size), "The model does not match the board size");
+            nn
+        });
+        let ntuple = self.ntuple.as_ref().map(|file| {
+            let network = NTupleNetwork::load(file).expect("Failed to load n-tuple network");
+            assert_eq!(
+                network.size(),
+                size,
+                "The n-tuple network does not match the board size"
+            );
+            Arc::new(network)
+        });
+        let (player, evaluator, search) = (self.player, self.evaluator, self.search);
+        move |seed| match &nn {
+            Some(nn) => Box::new(nn.clone()),
+            None => {
+                let evaluator = match &ntuple {
+                    Some(network) => Some(Box::new(Arc::clone(network)) as Box<dyn Evaluator>),
+                    None => evaluator.map(Evaluators::build),
+                };
+                player.build(search, evaluator, seed)
+            }
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Evaluators {
+    Score,
+    EmptyCells,
+    Monotonicity,
+    Smoothness,
+    CornerSnake,
+    Weighted,
+}
+
+impl Evaluators {
+    fn build(self) -> Box<dyn Evaluator> {
+        match self {
+            Evaluators::Score => Box::new(Score),
+            Evaluators::EmptyCells => Box::new(EmptyCells),
+            Evaluators::Monotonicity => Box::new(Monotonicity),
+            Evaluators::Smoothness => Box::new(Smoothness),
+            Evaluators::CornerSnake => Box::new(CornerSnake::default()),
+            Evaluators::Weighted => Box::new(Weighted::default()),
+        }
+    }
+}
+
+/// Parses the size of a board, which `Game` supports from 2 up to `MAX_SIZE`.
+fn board_size(arg: &str) -> Result<usize, String> {
+    let size: usize = arg.parse().map_err(|err| format!("{err}"))?;
+    if !(2..=MAX_SIZE).contains(&size) {
+        return Err(format!("must be between 2 and {MAX_SIZE}"));
+    }
+    Ok(size)
+}
 
 fn main() {
     let args = Arguments::parse();
@@ -41,33 +257,97 @@ fn main() {
                 <RenderGame />
             }
         }),
-        Some(Commands::Train { save, load }) => {
-            let rounds = 100;
-            let max_steps = 10000;
-            let evolution_steps = 10000;
-            let n_agents = 1000;
-
-            let mut population = match load {
+        Some(Commands::Train {
+            save,
+            load,
+            config,
+            checkpoint,
+            resume,
+            overrides,
+        }) => {
+            let (mut config, resumed) = match resume {
                 Some(file) => {
-                    let nn = NeuralNetwork::load(&file).expect("Failed to load NN");
-                    Population::from_nn(n_agents, nn)
+                    let checkpoint = Checkpoint::load(&file).expect("Failed to load checkpoint");
+                    println!("Resuming at step {}", checkpoint.population.evolution_step);
+                    (checkpoint.config, Some(checkpoint.population))
                 }
                 None => {
-                    let layers = &[16, 128, 64, 4];
-                    let act_funs = &[
-                        ActivationFunction::ReLU,
-                        ActivationFunction::ReLU,
-                        ActivationFunction::None,
-                    ];
-                    Population::new(n_agents, layers, act_funs)
+                    let config = match config {
+                        Some(file) => TrainConfig::load(&file).unwrap_or_else(|err| {
+                            eprintln!("Invalid configuration {file}: {err}");
+                            std::process::exit(1);
+                        }),
+                        None => TrainConfig::default(),
+                    };
+                    (config, None)
                 }
             };
-
-            for _ in 0..evolution_steps {
-                for _ in 0..rounds {
-                    population.play(max_steps);
-                    population.resert_agents();
+            overrides.apply(&mut config);
+            let nn = load.map(|file| {
+                let nn = NeuralNetwork::load(&file).expect("Failed to load NN");
+                // The loaded model decides the architecture.
+                config.encoding = nn.encoding;
+                config.layer_sizes = nn.layer_sizes();
+                config.activation_functions = nn
+                    .layers
+                    .iter()
+                    .map(|layer| layer.activation_function)
+                    .collect();
+                nn
+            });
+            // Checked once everything that sets the configuration has been applied.
+            if let Err(err) = config.validate() {
+                eprintln!("Invalid configuration: {err}");
+                std::process::exit(1);
+            }
+            let mut population = resumed.unwrap_or_else(|| {
+                let seed = *config
+                    .seed
+                    .get_or_insert_with(|| rand::thread_rng().gen_range(0..=MAX_SEED));
+                println!("Seed {seed}");
+                match nn {
+                    Some(nn) => Population::from_nn(config.agents, config.size, nn, seed),
+                    None => Population::new(
+                        config.agents,
+                        config.size,
+                        &config.layer_sizes,
+                        &config.activation_functions,
+                        config.encoding,
+                        seed,
+                    ),
                 }
+            });
+            if let Some(file) = &checkpoint {
+                let file = Path::new(file).with_extension("config.json");
+                config
+                    .save(&file.to_string_lossy())
+                    .expect("Failed to save configuration");
+            }
+            let TrainConfig {
+                rounds,
+                max_steps,
+                evolution_steps,
+                keep_proportion,
+                mutation_rate,
+                mutation_variation,
+                ..
+            } = config;
+            let selection = config.selection();
+            let model_hyperparameters = BTreeMap::from([
+                ("agents".to_string(), population.agents.len() as f64),
+                ("rounds".to_string(), rounds as f64),
+                ("max_steps".to_string(), max_steps as f64),
+                ("keep_proportion".to_string(), keep_proportion),
+                (
+                    "crossover_probability".to_string(),
+                    config.crossover_probability,
+                ),
+                ("mutation_rate".to_string(), mutation_rate),
+                ("mutation_variation".to_string(), mutation_variation),
+            ]);
+
+            while population.evolution_step < evolution_steps {
+                let seeds = population.play_generation(rounds, max_steps);
 
                 let best = population
                     .get_best_agent()
@@ -79,16 +359,176 @@ fn main() {
                         best.avg_score(),
                         best.get_highest_tile().expect("Error getting best tile")
                     );
+                    println!("Game seeds {}", seeds.iter().join(","));
                     if let Some(file) = save.clone() {
-                        best.nn.save(&file).expect("Failed to save model");
+                        let metadata = Metadata {
+                            evolution_step: Some(population.evolution_step),
+                            average_score: Some(best.avg_score()),
+                            hyperparameters: model_hyperparameters.clone(),
+                            game_seeds: seeds.clone(),
+                            ..Default::default()
+                        };
+                        best.nn
+                            .save_with_metadata(&file, &metadata)
+                            .expect("Failed to save model");
                     }
                 }
 
                 population.evolve(
-                    AGENTS_KEEP_PROPORTION,
-                    BRAIN_MUTATION_RATE,
-                    BRAIN_MUTATION_VARIATION,
+                    selection.as_ref(),
+                    config.crossover,
+                    config.crossover_probability,
+                    mutation_rate,
+                    mutation_variation,
                 );
+
+                // Saved between two steps, where the whole state of the run is in the population.
+                if let Some(file) = &checkpoint {
+                    if population.evolution_step % 10 == 0 {
+                        let checkpoint = Checkpoint {
+                            config: config.clone(),
+                            population: population.clone(),
+                        };
+                        checkpoint.save(file).expect("Failed to save checkpoint");
+                    }
+                }
+            }
+        }
+        Some(Commands::Replay { file }) => {
+            let replay = Replay::load(&file).expect("Failed to load replay");
+            match replay.validate() {
+                Ok(game) => println!(
+                    "Valid replay - {} moves - Score {} - highest tile {}",
+                    game.moves,
+                    game.score,
+                    game.highest_tile().expect("Error getting best tile")
+                ),
+                Err(err) => {
+                    eprintln!("Invalid replay: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Play {
+            seed,
+            size,
+            replay: file,
+            policy,
+        }) => {
+            let seed = seed.unwrap_or_else(rand::random);
+            println!("Seed {seed}");
+            let mut game = Game::with_size(size, seed);
+            let mut replay = Replay::new(&game);
+            let mut policy = policy.factory(size)(seed);
+
+            while let Some(action) = policy.choose(&game) {
+                if !replay.step(&mut game, action).changed {
+                    break;
+                }
+            }
+
+            println!(
+                "Game over - {} moves - Score {} - highest tile {}",
+                game.moves,
+                game.score,
+                game.highest_tile().expect("Error getting best tile")
+            );
+            if let Some(file) = file {
+                replay.save(&file).expect("Failed to save replay");
+            }
+        }
+        Some(Commands::Convert {
+            input,
+            output,
+            precision,
+        }) => {
+            let (nn, metadata) =
+                NeuralNetwork::load_with_metadata(&input).expect("Failed to load NN");
+            if output.ends_with(".json") {
+                nn.save_with_metadata(&output, &metadata)
+            } else {
+                nn.save_binary(&output, &metadata, precision)
+            }
+            .expect("Failed to save model");
+            let size = |file: &str| std::fs::metadata(file).map_or(0, |m| m.len());
+            println!(
+                "Converted {input} ({} bytes) to {output} ({} bytes)",
+                size(&input),
+                size(&output)
+            );
+        }
+        Some(Commands::Bench {
+            games,
+            seed,
+            size,
+            format,
+            policy,
+        }) => {
+            let seed = seed.unwrap_or_else(rand::random);
+            let start = Instant::now();
+            let results = bench::run(games, size, seed, policy.factory(size));
+            let stats = BenchStats::new(&results, start.elapsed());
+            match format {
+                OutputFormat::Table => print!("Seed {seed}\n{}", stats.table()),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&stats).expect("Failed to serialize statistics")
+                ),
+                OutputFormat::Csv => print!("{}", stats.csv()),
+            }
+        }
+        Some(Commands::Ntuple {
+            episodes,
+            learning_rate,
+            save,
+            load,
+            seed,
+            size,
+            patterns,
+        }) => {
+            let seed = seed.unwrap_or_else(rand::random);
+            println!("Seed {seed}");
+            let mut rng = SplitMix64::seed_from_u64(seed);
+            let mut network = match load {
+                Some(file) => {
+                    let network =
+                        NTupleNetwork::load(&file).expect("Failed to load n-tuple network");
+                    assert_eq!(
+                        network.size(),
+                        size,
+                        "The n-tuple network does not match the board size"
+                    );
+                    network
+                }
+                None => {
+                    let patterns = match patterns {
+                        Some(text) => ntuple::parse_patterns(&text, size)
+                            .unwrap_or_else(|err| panic!("Invalid patterns: {err}")),
+                        None => ntuple::default_patterns(size),
+                    };
+                    NTupleNetwork::new(size, patterns)
+                }
+            };
+
+            let log_every = 1000;
+            let (mut total_score, mut reached_2048) = (0, 0);
+            for episode in 1..=episodes {
+                let game = network.train_episode(learning_rate, &mut rng);
+                total_score += game.score as u64;
+                if game.highest_tile() >= Some(2048) {
+                    reached_2048 += 1;
+                }
+                if episode % log_every == 0 {
+                    println!(
+                        "Episode {episode:7} - Avg Score {:9.2} - 2048 rate {:5.1}%",
+                        total_score as f64 / log_every as f64,
+                        reached_2048 as f64 * 100.0 / log_every as f64
+                    );
+                    (total_score, reached_2048) = (0, 0);
+                    if let Some(file) = &save {
+                        network.save(file).expect("Failed to save n-tuple network");
+                    }
+                }
             }
         }
     };
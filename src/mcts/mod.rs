@@ -0,0 +1,87 @@
+use crate::evaluator::Evaluator;
+use crate::game::{Actions, Game};
+use crate::replay::Replay;
+use crate::search::SearchConfig;
+
+use rand::seq::IteratorRandom;
+use rand::Rng;
+
+mod tree;
+
+pub use tree::{ActionStats, Mcts};
+
+/// Flat Monte Carlo: picks the action with the highest mean value over random rollouts,
+/// scoring the end of every rollout with `evaluator`.
+/// It builds no tree and is kept as a baseline for [`Mcts`].
+/// The rollouts are spread evenly over the actions, so the search can stop at any time.
+/// All randomness comes from `rng`, so a seeded `rng` gives a reproducible result.
+pub fn simulation<E: Evaluator + ?Sized, R: Rng + ?Sized>(
+    game: &Game,
+    config: &SearchConfig,
+    evaluator: &E,
+    rng: &mut R,
+) -> Option<Actions> {
+    let deadline = config.deadline();
+    let valid_actions = game.valid_moves();
+    if valid_actions.is_empty() {
+        return None;
+    }
+
+    let afterstates = valid_actions
+        .iter()
+        .map(|&action| {
+            let mut afterstate = *game;
+            afterstate.make_move(action);
+            afterstate
+        })
+        .collect::<Vec<_>>();
+    let mut values = vec![0.0; valid_actions.len()];
+    let mut rollouts = vec![0; valid_actions.len()];
+
+    for n in 0..config.rollouts {
+        let i = n % valid_actions.len();
+        let mut search_game = afterstates[i];
+        search_game.add_tile_with(rng);
+        values[i] += evaluator.evaluate(&rollout(search_game, config.depth, rng));
+        rollouts[i] += 1;
+        if deadline.passed() {
+            break;
+        }
+    }
+
+    let mean = |i: usize| values[i] / rollouts[i].max(1) as f64;
+    (0..valid_actions.len())
+        .max_by(|&x, &y| mean(x).total_cmp(&mean(y)))
+        .map(|i| valid_actions[i])
+}
+
+/// Plays `game` until it is over, choosing every move with `simulation`,
+/// and returns the replay of the game.
+pub fn play_recorded<E: Evaluator + ?Sized, R: Rng + ?Sized>(
+    game: &mut Game,
+    config: &SearchConfig,
+    evaluator: &E,
+    rng: &mut R,
+) -> Replay {
+    let mut replay = Replay::new(game);
+    while let Some(action) = simulation(game, config, evaluator, rng) {
+        replay.step(game, action);
+    }
+    replay
+}
+
+/// Plays random moves from `game`, at most `depth`, and returns the game reached.
+fn rollout<R: Rng + ?Sized>(mut game: Game, depth: usize, rng: &mut R) -> Game {
+    let mut level = 1;
+    while !game.is_game_over() && level < depth {
+        let action = random_move(&game, rng);
+        game.step_with(action, rng);
+        level += 1;
+    }
+    game
+}
+
+fn random_move<R: Rng + ?Sized>(game: &Game, rng: &mut R) -> Actions {
+    let selected = game.valid_moves().into_iter().choose(rng);
+    selected.unwrap()
+}
@@ -0,0 +1,377 @@
+use crate::evaluator::{Evaluator, Score};
+use crate::game::{Actions, Game, Spawn};
+use crate::search::SearchConfig;
+
+use rand::Rng;
+
+/// The exploration constant of UCB1.
+pub const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+/// How the search reached a node from its parent.
+#[derive(Clone, Copy, Debug)]
+enum Edge {
+    Root,
+    /// A chance node, reached by the player sliding the tiles.
+    Action(Actions),
+    /// A decision node, reached by a tile spawning.
+    Spawn(Spawn),
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    game: Game,
+    edge: Edge,
+    visits: usize,
+    total_value: f64,
+    children: Vec<usize>,
+}
+
+impl Node {
+    fn new(game: Game, edge: Edge) -> Self {
+        Self {
+            game,
+            edge,
+            visits: 0,
+            total_value: 0.0,
+            children: vec![],
+        }
+    }
+
+    fn mean_value(&self) -> f64 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            self.total_value / self.visits as f64
+        }
+    }
+}
+
+/// The search statistics of an action at the root.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ActionStats {
+    pub action: Actions,
+    pub visits: usize,
+    pub mean_value: f64,
+}
+
+/// Monte Carlo tree search with UCB1 selection.
+///
+/// Decision nodes hold the positions where the player moves and chance nodes
+/// the afterstates where a tile spawns. Spawns are sampled, so a chance node
+/// only grows the children that were drawn. The value of a node is the mean
+/// value, according to the `evaluator`, of the games reached by the rollouts
+/// that went through it.
+///
+/// The tree is kept between searches: when `search` is called with the position
+/// after a real move, the matching subtree becomes the new root.
+#[derive(Clone, Debug)]
+pub struct Mcts<E = Score> {
+    pub config: SearchConfig,
+    pub exploration: f64,
+    pub evaluator: E,
+    nodes: Vec<Node>,
+}
+
+impl Default for Mcts {
+    fn default() -> Self {
+        Self::new(SearchConfig::default())
+    }
+}
+
+impl Mcts {
+    /// A search that values the games by their score.
+    pub fn new(config: SearchConfig) -> Self {
+        Self::with_evaluator(config, Score)
+    }
+}
+
+impl<E: Evaluator> Mcts<E> {
+    pub fn with_evaluator(config: SearchConfig, evaluator: E) -> Self {
+        Self {
+            config,
+            exploration: EXPLORATION,
+            evaluator,
+            nodes: vec![],
+        }
+    }
+
+    /// Runs one iteration per rollout of the config from `game`, or less if the
+    /// time budget runs out, and returns the most visited action,
+    /// or `None` if the game is over.
+    /// Without a time budget, a seeded `rng` gives a reproducible result.
+    pub fn search<R: Rng + ?Sized>(&mut self, game: &Game, rng: &mut R) -> Option<Actions> {
+        let deadline = self.config.deadline();
+        self.reuse(game);
+        for _ in 0..self.config.rollouts {
+            self.iterate(rng);
+            if deadline.passed() {
+                break;
+            }
+        }
+        self.stats()
+            .into_iter()
+            .max_by_key(|stats| stats.visits)
+            .map(|stats| stats.action)
+    }
+
+    /// Returns the statistics of every valid action at the root.
+    pub fn stats(&self) -> Vec<ActionStats> {
+        let Some(root) = self.nodes.first() else {
+            return vec![];
+        };
+        root.children
+            .iter()
+            .map(|&child| {
+                let node = &self.nodes[child];
+                let Edge::Action(action) = node.edge else {
+                    unreachable!("the children of a decision node are reached by actions")
+                };
+                ActionStats {
+                    action,
+                    visits: node.visits,
+                    mean_value: node.mean_value(),
+                }
+            })
+            .collect()
+    }
+
+    /// The number of iterations that went through the root.
+    pub fn root_visits(&self) -> usize {
+        self.nodes.first().map_or(0, |root| root.visits)
+    }
+
+    /// Drops the tree.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+    }
+
+    /// Makes the node of `game` the root, keeping its subtree.
+    /// `game` is looked up at the root and after one move and spawn,
+    /// otherwise the search starts from a new tree.
+    fn reuse(&mut self, game: &Game) {
+        let same = |node: &Node| node.game.board == game.board && node.game.score == game.score;
+
+        let Some(root) = self.nodes.first() else {
+            self.nodes.push(Node::new(*game, Edge::Root));
+            return;
+        };
+        if same(root) {
+            return;
+        }
+        let found = root
+            .children
+            .iter()
+            .flat_map(|&chance| self.nodes[chance].children.iter().copied())
+            .find(|&decision| same(&self.nodes[decision]));
+        match found {
+            Some(decision) => self.reroot(decision),
+            None => {
+                self.nodes.clear();
+                self.nodes.push(Node::new(*game, Edge::Root));
+            }
+        }
+    }
+
+    /// Copies the subtree of `root` into a new arena.
+    fn reroot(&mut self, root: usize) {
+        let mut old: Vec<Option<Node>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut node = old[root].take().expect("the new root is in the tree");
+        node.edge = Edge::Root;
+        self.nodes.push(node);
+
+        // Breadth first, so the children of a node are pushed after it.
+        let mut index = 0;
+        while index < self.nodes.len() {
+            let children = std::mem::take(&mut self.nodes[index].children);
+            self.nodes[index].children = children
+                .into_iter()
+                .map(|child| {
+                    let node = old[child].take().expect("every node has one parent");
+                    self.nodes.push(node);
+                    self.nodes.len() - 1
+                })
+                .collect();
+            index += 1;
+        }
+    }
+
+    /// Selects a path down the tree, expands a new node, rolls out from it
+    /// and backpropagates the value along the path.
+    fn iterate<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        let mut path = vec![0];
+        let mut decision = 0;
+
+        let value = loop {
+            if self.nodes[decision].children.is_empty() {
+                self.expand(decision);
+            }
+            let Some(chance) = self.select(decision) else {
+                // No valid move, the game is over.
+                break self.evaluator.evaluate(&self.nodes[decision].game);
+            };
+            path.push(chance);
+
+            let mut game = self.nodes[chance].game;
+            let spawn = game.add_tile_with(rng).expect("a move always frees a cell");
+            let existing = self.nodes[chance]
+                .children
+                .iter()
+                .copied()
+                .find(|&child| matches!(self.nodes[child].edge, Edge::Spawn(s) if s == spawn));
+            match existing {
+                Some(child) => {
+                    path.push(child);
+                    decision = child;
+                }
+                None => {
+                    self.nodes.push(Node::new(game, Edge::Spawn(spawn)));
+                    let child = self.nodes.len() - 1;
+                    self.nodes[chance].children.push(child);
+                    path.push(child);
+                    let end = super::rollout(game, self.config.depth, rng);
+                    break self.evaluator.evaluate(&end);
+                }
+            }
+        };
+
+        for index in path {
+            let node = &mut self.nodes[index];
+            node.visits += 1;
+            node.total_value += value;
+        }
+    }
+
+    /// Adds a chance node for every valid action.
+    fn expand(&mut self, decision: usize) {
+        let game = self.nodes[decision].game;
+        for action in game.valid_moves() {
+            if let Some(afterstate) = game.slide(action) {
+                self.nodes.push(Node::new(afterstate, Edge::Action(action)));
+                let child = self.nodes.len() - 1;
+                self.nodes[decision].children.push(child);
+            }
+        }
+    }
+
+    /// Picks an unvisited child first, then the child with the highest UCB1 bound.
+    /// Mean values are rescaled to `[0, 1]` among the siblings, as scores are unbounded.
+    fn select(&self, decision: usize) -> Option<usize> {
+        let node = &self.nodes[decision];
+        let children = &node.children;
+        if let Some(&unvisited) = children.iter().find(|&&c| self.nodes[c].visits == 0) {
+            return Some(unvisited);
+        }
+
+        let means = children.iter().map(|&c| self.nodes[c].mean_value());
+        let min = means.clone().fold(f64::INFINITY, f64::min);
+        let max = means.fold(f64::NEG_INFINITY, f64::max);
+        let range = if max > min { max - min } else { 1.0 };
+        let log_visits = (node.visits as f64).ln();
+
+        children.iter().copied().max_by(|&x, &y| {
+            let bound = |c: usize| {
+                let child = &self.nodes[c];
+                (child.mean_value() - min) / range
+                    + self.exploration * (log_visits / child.visits as f64).sqrt()
+            };
+            bound(x).total_cmp(&bound(y))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
+
+    fn config(rollouts: usize) -> SearchConfig {
+        SearchConfig {
+            rollouts,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn no_move_when_game_over() {
+        let mut game = Game::with_seed(0);
+        for (i, value) in [1, 2, 3, 4, 2, 3, 4, 1, 1, 2, 3, 4, 2, 3, 4, 1]
+            .into_iter()
+            .enumerate()
+        {
+            game.set(i / 4, i % 4, value);
+        }
+        let mut mcts = Mcts::new(config(10));
+        assert_eq!(mcts.search(&game, &mut SplitMix64::seed_from_u64(0)), None);
+        assert!(mcts.stats().is_empty());
+    }
+
+    #[test]
+    fn stats_cover_the_valid_moves() {
+        let game = Game::with_seed(3);
+        let mut mcts = Mcts::new(config(100));
+        let action = mcts
+            .search(&game, &mut SplitMix64::seed_from_u64(0))
+            .unwrap();
+
+        let stats = mcts.stats();
+        assert_eq!(stats.len(), game.valid_moves().len());
+        assert_eq!(stats.iter().map(|s| s.visits).sum::<usize>(), 100);
+        assert_eq!(mcts.root_visits(), 100);
+        let best = stats.iter().max_by_key(|s| s.visits).unwrap();
+        assert_eq!(best.action, action);
+        assert!(stats.iter().all(|s| s.mean_value >= game.score as f64));
+    }
+
+    #[test]
+    fn stops_when_time_runs_out() {
+        let game = Game::with_seed(3);
+        let mut mcts = Mcts::new(SearchConfig {
+            time_budget_ms: Some(0),
+            ..Default::default()
+        });
+        assert!(mcts
+            .search(&game, &mut SplitMix64::seed_from_u64(0))
+            .is_some());
+        assert_eq!(mcts.root_visits(), 1);
+    }
+
+    #[test]
+    fn reuses_the_subtree() {
+        let mut game = Game::with_seed(5);
+        let mut rng = SplitMix64::seed_from_u64(1);
+        let mut mcts = Mcts::new(config(500));
+        let action = mcts.search(&game, &mut rng).unwrap();
+        game.step(action);
+
+        // Search without rollouts to only move the root.
+        mcts.config.rollouts = 0;
+        mcts.search(&game, &mut rng);
+        let kept = mcts.root_visits();
+        assert!(kept > 0);
+        assert_eq!(mcts.nodes[0].game.board, game.board);
+
+        mcts.config.rollouts = 100;
+        mcts.search(&game, &mut rng);
+        assert_eq!(mcts.root_visits(), kept + 100);
+
+        // An unrelated position starts a new tree.
+        let other = Game::with_seed(6);
+        mcts.search(&other, &mut rng);
+        assert_eq!(mcts.root_visits(), 100);
+    }
+
+    #[test]
+    fn reproducible_with_seed() {
+        let game = Game::with_seed(7);
+        let run = || {
+            let mut mcts = Mcts::new(config(200));
+            mcts.search(&game, &mut SplitMix64::seed_from_u64(9));
+            mcts.stats()
+        };
+        assert_eq!(run(), run());
+    }
+}
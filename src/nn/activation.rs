@@ -19,10 +19,31 @@ pub fn tanh(x: f64) -> f64 {
     x.tanh()
 }
 
+/// Computes the derivative of the sigmoid function.
+pub fn sigmoid_derivative(x: f64) -> f64 {
+    let s = sigmoid(x);
+    s * (1.0 - s)
+}
+
+/// Computes the derivative of the ReLU function, taking 0 at 0.
+pub fn relu_derivative(x: f64) -> f64 {
+    if x > 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Computes the derivative of the tanh function.
+pub fn tanh_derivative(x: f64) -> f64 {
+    1.0 - x.tanh().powi(2)
+}
+
 /// Enumeration of possible activation functions.
-#[derive(Clone, Copy, Serialize, Deserialize)]
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ActivationFunction {
     Sigmoid,
+    #[value(name = "relu")]
     ReLU,
     Tanh,
     None,
@@ -38,4 +59,35 @@ impl ActivationFunction {
             ActivationFunction::None => x,
         }
     }
+
+    /// Returns the derivative of the activation function at the given input.
+    pub fn derivative(&self, x: f64) -> f64 {
+        match self {
+            ActivationFunction::Sigmoid => sigmoid_derivative(x),
+            ActivationFunction::ReLU => relu_derivative(x),
+            ActivationFunction::Tanh => tanh_derivative(x),
+            ActivationFunction::None => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        let h = 1e-6;
+        for function in [
+            ActivationFunction::Sigmoid,
+            ActivationFunction::ReLU,
+            ActivationFunction::Tanh,
+            ActivationFunction::None,
+        ] {
+            for x in [-2.0, -0.5, 0.3, 1.7] {
+                let numeric = (function.activate(x + h) - function.activate(x - h)) / (2.0 * h);
+                assert!((function.derivative(x) - numeric).abs() < 1e-6);
+            }
+        }
+    }
 }
@@ -0,0 +1,383 @@
+//! A compact binary model format, little-endian throughout:
+//!
+//! | bytes | content |
+//! | ----- | ------- |
+//! | 4 | [`MAGIC`] |
+//! | 4 | format version, see [`BINARY_VERSION`] |
+//! | 1 | bytes per weight, 4 or 8 |
+//! | 1 | input encoding |
+//! | 4 | number of layers |
+//! | 9 per layer | inputs (4), outputs (4) and activation function (1) |
+//! | 4 + n | the metadata as `n` bytes of JSON |
+//! | rest | the weights and then the biases of every layer |
+
+use super::activation::ActivationFunction;
+use super::encoding::InputEncoding;
+use super::model::{LoadError, Metadata};
+use super::{Layer, NeuralNetwork};
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+/// The first bytes of every binary model file.
+pub const MAGIC: [u8; 4] = *b"N2KM";
+/// The version of the binary format.
+pub const BINARY_VERSION: u32 = 1;
+/// The most weights and biases a binary model may have, far more than any network trained here,
+/// so a corrupt file is rejected before its sizes are trusted.
+pub const MAX_PARAMETERS: usize = 1 << 26;
+
+/// The precision the weights are stored with.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Precision {
+    /// 4 bytes per weight, half the size, which is plenty for playing
+    #[default]
+    F32,
+    /// 8 bytes per weight, exactly the weights in memory
+    F64,
+}
+
+impl Precision {
+    fn bytes(self) -> u8 {
+        match self {
+            Precision::F32 => 4,
+            Precision::F64 => 8,
+        }
+    }
+}
+
+fn activation_id(function: ActivationFunction) -> u8 {
+    match function {
+        ActivationFunction::Sigmoid => 0,
+        ActivationFunction::ReLU => 1,
+        ActivationFunction::Tanh => 2,
+        ActivationFunction::None => 3,
+    }
+}
+
+fn activation_from_id(id: u8) -> Result<ActivationFunction, LoadError> {
+    match id {
+        0 => Ok(ActivationFunction::Sigmoid),
+        1 => Ok(ActivationFunction::ReLU),
+        2 => Ok(ActivationFunction::Tanh),
+        3 => Ok(ActivationFunction::None),
+        _ => Err(LoadError::InvalidBinary(format!(
+            "unknown activation function {id}"
+        ))),
+    }
+}
+
+fn encoding_id(encoding: InputEncoding) -> u8 {
+    match encoding {
+        InputEncoding::Raw => 0,
+        InputEncoding::Normalized => 1,
+        InputEncoding::OneHot => 2,
+        InputEncoding::Pairs => 3,
+    }
+}
+
+fn encoding_from_id(id: u8) -> Result<InputEncoding, LoadError> {
+    match id {
+        0 => Ok(InputEncoding::Raw),
+        1 => Ok(InputEncoding::Normalized),
+        2 => Ok(InputEncoding::OneHot),
+        3 => Ok(InputEncoding::Pairs),
+        _ => Err(LoadError::InvalidBinary(format!(
+            "unknown input encoding {id}"
+        ))),
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Reads the next `len` bytes, without allocating them up front,
+/// so a length past the end of the file fails before using the memory.
+fn read_bytes<R: Read>(reader: &mut R, len: usize, what: &str) -> Result<Vec<u8>, LoadError> {
+    let mut buffer = vec![];
+    reader.by_ref().take(len as u64).read_to_end(&mut buffer)?;
+    if buffer.len() != len {
+        return Err(LoadError::InvalidBinary(format!(
+            "the {what} take {len} bytes, but only {} are left",
+            buffer.len()
+        )));
+    }
+    Ok(buffer)
+}
+
+/// Reads `count` weights stored with `bytes` bytes each.
+fn read_weights<R: Read>(reader: &mut R, count: usize, bytes: u8) -> Result<Vec<f64>, LoadError> {
+    let len = count
+        .checked_mul(bytes as usize)
+        .ok_or_else(|| LoadError::InvalidBinary("too many weights".into()))?;
+    let buffer = read_bytes(reader, len, "weights")?;
+    let weights = match bytes {
+        4 => buffer
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()) as f64)
+            .collect(),
+        _ => buffer
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect(),
+    };
+    Ok(weights)
+}
+
+fn write_weights<W: Write>(
+    writer: &mut W,
+    weights: &[f64],
+    precision: Precision,
+) -> io::Result<()> {
+    for &weight in weights {
+        match precision {
+            Precision::F32 => writer.write_all(&(weight as f32).to_le_bytes())?,
+            Precision::F64 => writer.write_all(&weight.to_le_bytes())?,
+        }
+    }
+    Ok(())
+}
+
+impl NeuralNetwork {
+    /// Writes the neural network with `metadata` in the binary format.
+    pub fn write_binary<W: Write>(
+        &self,
+        writer: &mut W,
+        metadata: &Metadata,
+        precision: Precision,
+    ) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&BINARY_VERSION.to_le_bytes())?;
+        writer.write_all(&[precision.bytes(), encoding_id(self.encoding)])?;
+        writer.write_all(&(self.layers.len() as u32).to_le_bytes())?;
+        for layer in &self.layers {
+            writer.write_all(&(layer.input_size() as u32).to_le_bytes())?;
+            writer.write_all(&(layer.output_size() as u32).to_le_bytes())?;
+            writer.write_all(&[activation_id(layer.activation_function)])?;
+        }
+        let metadata = serde_json::to_vec(metadata)?;
+        writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+        writer.write_all(&metadata)?;
+        for layer in &self.layers {
+            write_weights(writer, &layer.weights, precision)?;
+            write_weights(writer, &layer.biases, precision)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a neural network and its metadata in the binary format.
+    pub fn read_binary<R: Read>(reader: &mut R) -> Result<(Self, Metadata), LoadError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(LoadError::InvalidBinary("not a binary model".into()));
+        }
+        let version = read_u32(reader)?;
+        if version != BINARY_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+        let bytes = read_u8(reader)?;
+        if bytes != 4 && bytes != 8 {
+            return Err(LoadError::InvalidBinary(format!(
+                "unsupported weights of {bytes} bytes"
+            )));
+        }
+        let encoding = encoding_from_id(read_u8(reader)?)?;
+
+        let mut shapes = vec![];
+        let mut parameters: usize = 0;
+        for _ in 0..read_u32(reader)? {
+            let input_size = read_u32(reader)? as usize;
+            let output_size = read_u32(reader)? as usize;
+            let activation_function = activation_from_id(read_u8(reader)?)?;
+            if input_size == 0 || output_size == 0 {
+                return Err(LoadError::InvalidBinary("empty layer".into()));
+            }
+            if let Some(&(_, previous, _)) = shapes.last() {
+                if input_size != previous {
+                    return Err(LoadError::ShapeMismatch {
+                        layer: shapes.len(),
+                        expected: previous,
+                        found: input_size,
+                    });
+                }
+            }
+            parameters = input_size
+                .checked_mul(output_size)
+                .and_then(|weights| weights.checked_add(output_size))
+                .and_then(|layer| layer.checked_add(parameters))
+                .filter(|&parameters| parameters <= MAX_PARAMETERS)
+                .ok_or_else(|| {
+                    LoadError::InvalidBinary(format!(
+                        "more than {MAX_PARAMETERS} weights and biases"
+                    ))
+                })?;
+            shapes.push((input_size, output_size, activation_function));
+        }
+        Self::check_outputs(shapes.last().map_or(0, |&(_, output_size, _)| output_size))?;
+
+        let len = read_u32(reader)? as usize;
+        let metadata = read_bytes(reader, len, "metadata")?;
+        let metadata = serde_json::from_slice(&metadata)?;
+
+        let mut layers = vec![];
+        for (input_size, output_size, activation_function) in shapes {
+            let weights = read_weights(reader, input_size * output_size, bytes)?;
+            let biases = read_weights(reader, output_size, bytes)?;
+            layers.push(Layer::from_weights(
+                input_size,
+                weights,
+                biases,
+                activation_function,
+            ));
+        }
+        Ok((NeuralNetwork { layers, encoding }, metadata))
+    }
+
+    /// Returns the neural network with `metadata` in the binary format.
+    pub fn to_bytes(&self, metadata: &Metadata, precision: Precision) -> Vec<u8> {
+        let mut bytes = vec![];
+        self.write_binary(&mut bytes, metadata, precision)
+            .expect("Writing to memory cannot fail");
+        bytes
+    }
+
+    /// Reads a neural network and its metadata from bytes in the binary format,
+    /// such as a model included in the binary.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<(Self, Metadata), LoadError> {
+        Self::read_binary(&mut bytes)
+    }
+
+    /// Saves the neural network with `metadata` to a file in the binary format,
+    /// setting the creation time to now unless it is already set.
+    pub fn save_binary(
+        &self,
+        filename: &str,
+        metadata: &Metadata,
+        precision: Precision,
+    ) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        self.write_binary(&mut writer, &metadata.stamped(), precision)?;
+        writer.flush()
+    }
+
+    /// Loads a neural network and its metadata from a file in the binary format.
+    pub fn load_binary(filename: &str) -> Result<(Self, Metadata), LoadError> {
+        Self::read_binary(&mut BufReader::new(File::open(filename)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
+    use std::collections::BTreeMap;
+
+    fn network() -> NeuralNetwork {
+        NeuralNetwork::new(
+            &[16, 8, 4],
+            &[ActivationFunction::Tanh, ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(0),
+        )
+        .with_encoding(InputEncoding::Normalized)
+    }
+
+    #[test]
+    fn round_trips() {
+        let nn = network();
+        let metadata = Metadata {
+            evolution_step: Some(7),
+            hyperparameters: BTreeMap::from([("agents".to_string(), 10.0)]),
+            ..Default::default()
+        };
+
+        let bytes = nn.to_bytes(&metadata, Precision::F64);
+        let (loaded, loaded_metadata) = NeuralNetwork::from_bytes(&bytes).unwrap();
+        assert_eq!(loaded.layers, nn.layers);
+        assert_eq!(loaded.encoding, nn.encoding);
+        assert_eq!(loaded_metadata, metadata);
+
+        let small = nn.to_bytes(&metadata, Precision::F32);
+        assert_eq!(bytes.len() - small.len(), 4 * (16 * 8 + 8 + 8 * 4 + 4));
+        let (loaded, _) = NeuralNetwork::from_bytes(&small).unwrap();
+        for (layer, loaded) in nn.layers.iter().zip(&loaded.layers) {
+            let weights = layer.weights.iter().zip(&loaded.weights);
+            assert!(weights.clone().all(|(w, l)| (w - l).abs() < 1e-6));
+            assert!(weights.clone().any(|(w, l)| w != l));
+        }
+    }
+
+    #[test]
+    fn files_load_in_either_format() {
+        let nn = network();
+        let filename = "test_model.bin";
+        nn.save_binary(filename, &Metadata::default(), Precision::F64)
+            .unwrap();
+        let loaded = NeuralNetwork::load(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(loaded.layers, nn.layers);
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let bytes = network().to_bytes(&Metadata::default(), Precision::F32);
+        let result = NeuralNetwork::from_bytes(&bytes[..bytes.len() - 1]);
+        assert!(matches!(result, Err(LoadError::InvalidBinary(_))));
+        let result = NeuralNetwork::from_bytes(&bytes[..10]);
+        assert!(matches!(result, Err(LoadError::Io(_))));
+
+        // A first layer of 2^32 - 1 inputs, which would not fit in memory.
+        let mut huge = bytes.clone();
+        huge[14..18].copy_from_slice(&u32::MAX.to_le_bytes());
+        let result = NeuralNetwork::from_bytes(&huge);
+        assert!(matches!(result, Err(LoadError::InvalidBinary(_))));
+
+        // Metadata that claims to be longer than the rest of the file.
+        let mut long = bytes.clone();
+        let metadata = 14 + 9 * 2;
+        long[metadata..metadata + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let result = NeuralNetwork::from_bytes(&long);
+        assert!(matches!(result, Err(LoadError::InvalidBinary(_))));
+        let result = NeuralNetwork::from_bytes(b"{\"layers\": []}");
+        assert!(matches!(result, Err(LoadError::InvalidBinary(_))));
+
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        let result = NeuralNetwork::from_bytes(&newer);
+        assert!(matches!(result, Err(LoadError::UnsupportedVersion(2))));
+
+        // The second layer claims 9 inputs for the 8 outputs of the first.
+        let mut mismatched = bytes;
+        mismatched[14 + 9] = 9;
+        let result = NeuralNetwork::from_bytes(&mismatched);
+        assert!(matches!(
+            result,
+            Err(LoadError::ShapeMismatch { layer: 1, .. })
+        ));
+
+        let narrow = NeuralNetwork::new(
+            &[16, 2],
+            &[ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(1),
+        );
+        let result =
+            NeuralNetwork::from_bytes(&narrow.to_bytes(&Metadata::default(), Precision::F32));
+        assert!(matches!(
+            result,
+            Err(LoadError::OutputSize {
+                expected: 4,
+                found: 2
+            })
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+//! Crossover operators, which make a child network out of two parents with
+//! the same architecture.
+
+use super::{Layer, NeuralNetwork};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// How the parameters of two parents are combined.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Crossover {
+    /// Every weight and bias from either parent
+    #[default]
+    Uniform,
+    /// The weights and the bias of every node from either parent
+    Node,
+    /// Every layer from either parent
+    Layer,
+    /// A weighted average of the parents, with a random weight per layer
+    Blend,
+}
+
+impl Layer {
+    /// Returns a child of the layer and `other`, which must have the same shape.
+    pub fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &Layer,
+        method: Crossover,
+        rng: &mut R,
+    ) -> Self {
+        assert!(self.input_size() == other.input_size());
+        assert!(self.output_size() == other.output_size());
+        let mut child = self.clone();
+        match method {
+            Crossover::Uniform => {
+                let parameters = child.weights.iter_mut().chain(&mut child.biases);
+                let others = other.weights.iter().chain(&other.biases);
+                for (parameter, other) in parameters.zip(others) {
+                    if rng.gen() {
+                        *parameter = *other;
+                    }
+                }
+            }
+            Crossover::Node => {
+                let input_size = self.input_size();
+                let rows = child.weights.chunks_exact_mut(input_size);
+                for (output, (row, bias)) in rows.zip(&mut child.biases).enumerate() {
+                    if rng.gen() {
+                        row.copy_from_slice(other.row(output));
+                        *bias = other.biases[output];
+                    }
+                }
+            }
+            Crossover::Layer => {
+                if rng.gen() {
+                    child = other.clone();
+                }
+            }
+            Crossover::Blend => {
+                let alpha = rng.gen::<f64>();
+                let parameters = child.weights.iter_mut().chain(&mut child.biases);
+                let others = other.weights.iter().chain(&other.biases);
+                for (parameter, other) in parameters.zip(others) {
+                    *parameter = alpha * *parameter + (1.0 - alpha) * other;
+                }
+            }
+        }
+        child
+    }
+}
+
+impl NeuralNetwork {
+    /// Returns a child of the network and `other`, which must have the same
+    /// layer sizes, activation functions and encoding.
+    pub fn crossover<R: Rng + ?Sized>(
+        &self,
+        other: &NeuralNetwork,
+        method: Crossover,
+        rng: &mut R,
+    ) -> Self {
+        assert!(self.layers.len() == other.layers.len());
+        assert!(self.encoding == other.encoding);
+        let layers = self
+            .layers
+            .iter()
+            .zip(&other.layers)
+            .map(|(layer, other)| {
+                assert!(layer.activation_function == other.activation_function);
+                layer.crossover(other, method, rng)
+            })
+            .collect();
+        NeuralNetwork {
+            layers,
+            encoding: self.encoding,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::activation::ActivationFunction;
+    use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
+
+    fn constant(value: f64) -> NeuralNetwork {
+        NeuralNetwork {
+            layers: vec![
+                Layer::from_weights(3, vec![value; 6], vec![value; 2], ActivationFunction::ReLU),
+                Layer::from_weights(2, vec![value; 4], vec![value; 2], ActivationFunction::None),
+            ],
+            encoding: Default::default(),
+        }
+    }
+
+    fn parameters(layer: &Layer) -> Vec<f64> {
+        layer.weights.iter().chain(&layer.biases).copied().collect()
+    }
+
+    #[test]
+    fn children_mix_their_parents() {
+        let (a, b) = (constant(0.0), constant(1.0));
+        let mut rng = SplitMix64::seed_from_u64(0);
+        let mut mixed = [false; 4];
+        for _ in 0..20 {
+            for (i, method) in [
+                Crossover::Uniform,
+                Crossover::Node,
+                Crossover::Layer,
+                Crossover::Blend,
+            ]
+            .into_iter()
+            .enumerate()
+            {
+                let child = a.crossover(&b, method, &mut rng);
+                assert_eq!(child.layer_sizes(), a.layer_sizes());
+                for layer in &child.layers {
+                    let parameters = parameters(layer);
+                    assert!(parameters.iter().all(|p| (0.0..=1.0).contains(p)));
+                    match method {
+                        Crossover::Uniform => {
+                            assert!(parameters.iter().all(|&p| p == 0.0 || p == 1.0))
+                        }
+                        Crossover::Node => {
+                            for output in 0..layer.output_size() {
+                                let bias = layer.biases[output];
+                                assert!(layer.row(output).iter().all(|&w| w == bias));
+                            }
+                        }
+                        Crossover::Layer | Crossover::Blend => {
+                            assert!(parameters.iter().all(|&p| p == parameters[0]))
+                        }
+                    }
+                }
+                let all = child.layers.iter().flat_map(parameters).collect::<Vec<_>>();
+                mixed[i] |= all.iter().any(|&p| p != all[0]);
+            }
+        }
+        // Every method gives children that are not a copy of either parent.
+        assert!(mixed.iter().all(|&mixed| mixed));
+    }
+
+    #[test]
+    #[should_panic]
+    fn parents_must_have_the_same_shape() {
+        let other = NeuralNetwork::new(
+            &[3, 4, 2],
+            &[ActivationFunction::ReLU, ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(0),
+        );
+        constant(0.0).crossover(
+            &other,
+            Crossover::Uniform,
+            &mut SplitMix64::seed_from_u64(0),
+        );
+    }
+}
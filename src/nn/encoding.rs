@@ -0,0 +1,96 @@
+use crate::game::{Game, MAX_CELL, MAX_EXPONENT};
+use serde::{Deserialize, Serialize};
+
+/// How a board is turned into the inputs of a neural network.
+///
+/// The encoding is saved with the model, so a loaded model always
+/// sees boards the way it was trained on them.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputEncoding {
+    /// The exponent of every cell, 0 for empty cells
+    #[default]
+    Raw,
+    /// The exponent of every cell divided by 15, so tiles up to 2^15 give inputs
+    /// in [0, 1] and larger ones, on any board size, go beyond 1
+    Normalized,
+    /// One input per cell and exponent, which is 1 for the exponent of the cell
+    OneHot,
+    /// The normalized exponents, followed by one input per pair of neighbouring
+    /// cells in the rows and then the columns, which is 1 if the pair can merge
+    Pairs,
+}
+
+impl InputEncoding {
+    /// Returns the number of inputs for a board of `size` rows and columns.
+    pub fn input_size(self, size: usize) -> usize {
+        match self {
+            InputEncoding::Raw | InputEncoding::Normalized => size * size,
+            InputEncoding::OneHot => size * size * MAX_EXPONENT,
+            InputEncoding::Pairs => size * size + 2 * size * (size - 1),
+        }
+    }
+
+    /// Encodes the board of `game`.
+    pub fn encode(self, game: &Game) -> Vec<f64> {
+        let mut inputs = Vec::with_capacity(self.input_size(game.size()));
+        self.encode_into(game, &mut inputs);
+        inputs
+    }
+
+    /// Appends the encoding of the board of `game` to `inputs`,
+    /// so the boards of a batch can share one buffer.
+    pub fn encode_into(self, game: &Game, inputs: &mut Vec<f64>) {
+        let normalized = game.cells().map(|cell| cell as f64 / MAX_CELL as f64);
+        match self {
+            InputEncoding::Raw => inputs.extend(game.cells().map(|cell| cell as f64)),
+            InputEncoding::Normalized => inputs.extend(normalized),
+            InputEncoding::OneHot => inputs.extend(game.one_hot_encode_board()),
+            InputEncoding::Pairs => {
+                inputs.extend(normalized);
+                let size = game.size();
+                let merges = |a: u32, b: u32| if a != 0 && a == b { 1.0 } else { 0.0 };
+                for i in 0..size {
+                    for j in 1..size {
+                        inputs.push(merges(game.get(i, j - 1), game.get(i, j)));
+                    }
+                }
+                for j in 0..size {
+                    for i in 1..size {
+                        inputs.push(merges(game.get(i - 1, j), game.get(i, j)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    #[test]
+    fn encodings_have_their_input_size() {
+        for size in 2..=5 {
+            let game = Game::with_size(size, 1);
+            for &encoding in InputEncoding::value_variants() {
+                let inputs = encoding.encode(&game);
+                assert_eq!(inputs.len(), encoding.input_size(size), "{encoding:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn pairs_mark_merges() {
+        let mut game = Game::with_size(3, 0);
+        for (k, cell) in [1, 1, 2, 0, 0, 2, 3, 4, 5].into_iter().enumerate() {
+            game.set(k / 3, k % 3, cell);
+        }
+        let inputs = InputEncoding::Pairs.encode(&game);
+        assert_eq!(inputs[1], 1.0 / MAX_CELL as f64);
+        // The rows, where only the first two cells merge; empty cells never do.
+        assert_eq!(inputs[9..15], [1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        // The columns, where the 2s of the last column merge.
+        assert_eq!(inputs[15..21], [0.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+    }
+}
@@ -1,70 +1,302 @@
 use super::activation::ActivationFunction;
-use super::node::Node;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-/// A layer in a neural network, consisting of multiple nodes.
-#[derive(Clone, Serialize, Deserialize)]
+/// The gradients of a loss with respect to the weights and biases of a layer,
+/// with the same shape as its weights and biases.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradients {
+    pub weights: Vec<f64>,
+    pub biases: Vec<f64>,
+}
+
+impl Gradients {
+    /// Returns all-zero gradients with the shape of `layer`.
+    pub fn zeros(layer: &Layer) -> Self {
+        Gradients {
+            weights: vec![0.0; layer.weights.len()],
+            biases: vec![0.0; layer.biases.len()],
+        }
+    }
+
+    /// Adds `other` scaled by `scale` to the gradients.
+    pub fn add_scaled(&mut self, other: &Gradients, scale: f64) {
+        for (weight, other) in self.weights.iter_mut().zip(&other.weights) {
+            *weight += scale * other;
+        }
+        for (bias, other) in self.biases.iter_mut().zip(&other.biases) {
+            *bias += scale * other;
+        }
+    }
+}
+
+/// A dense layer in a neural network.
+///
+/// The weights are stored as one contiguous row-major matrix with a row of
+/// `input_size` weights per output, so the inner loops run over memory in order.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "LayerFormat")]
 pub struct Layer {
-    pub nodes: Vec<Node>,
+    input_size: usize,
+    pub weights: Vec<f64>,
+    pub biases: Vec<f64>,
     pub activation_function: ActivationFunction,
 }
 
+/// A node of the format before the weight matrices, with the weights of one output.
+#[derive(Deserialize)]
+struct NodeFormat {
+    weights: Vec<f64>,
+    bias: f64,
+}
+
+/// The serialized formats of a layer, so models saved as a list of nodes still load.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LayerFormat {
+    Matrix {
+        input_size: usize,
+        weights: Vec<f64>,
+        biases: Vec<f64>,
+        activation_function: ActivationFunction,
+    },
+    Nodes {
+        nodes: Vec<NodeFormat>,
+        activation_function: ActivationFunction,
+    },
+}
+
+impl TryFrom<LayerFormat> for Layer {
+    type Error = String;
+
+    fn try_from(format: LayerFormat) -> Result<Self, Self::Error> {
+        let (input_size, weights, biases, activation_function) = match format {
+            LayerFormat::Matrix {
+                input_size,
+                weights,
+                biases,
+                activation_function,
+            } => (input_size, weights, biases, activation_function),
+            LayerFormat::Nodes {
+                nodes,
+                activation_function,
+            } => {
+                let input_size = nodes.first().map_or(0, |node| node.weights.len());
+                if nodes.iter().any(|node| node.weights.len() != input_size) {
+                    return Err("the nodes of a layer have different numbers of weights".into());
+                }
+                let biases = nodes.iter().map(|node| node.bias).collect();
+                let weights = nodes.into_iter().flat_map(|node| node.weights).collect();
+                (input_size, weights, biases, activation_function)
+            }
+        };
+        if input_size == 0 || biases.is_empty() {
+            return Err("a layer needs at least one input and one output".into());
+        }
+        if weights.len() != input_size * biases.len() {
+            return Err(format!(
+                "expected {} weights for {input_size} inputs and {} outputs, got {}",
+                input_size * biases.len(),
+                biases.len(),
+                weights.len()
+            ));
+        }
+        Ok(Layer {
+            input_size,
+            weights,
+            biases,
+            activation_function,
+        })
+    }
+}
+
+/// The dot product of two slices of the same length, summed in four lanes
+/// so the compiler can vectorise the loop.
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    let mut lanes = [0.0; 4];
+    let (a_chunks, b_chunks) = (a.chunks_exact(4), b.chunks_exact(4));
+    let rest = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum::<f64>();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for k in 0..4 {
+            lanes[k] += x[k] * y[k];
+        }
+    }
+    (lanes[0] + lanes[1]) + (lanes[2] + lanes[3]) + rest
+}
+
 impl Layer {
     /// Creates a new layer with the given number of
-    /// input and output nodes, and an activation function.
+    /// inputs and outputs, and an activation function.
     ///
     /// # Arguments
     ///
-    /// * `input_size` - The number of inputs to each node in the layer.
-    /// * `output_size` - The number of nodes in the layer.
+    /// * `input_size` - The number of inputs of the layer.
+    /// * `output_size` - The number of outputs of the layer.
     /// * `activation_function` - The activation function
-    ///   to apply to the output of each node.
+    ///   to apply to each output.
+    /// * `rng` - The random number generator used to initialise the weights.
     ///
     /// # Returns
     ///
-    /// A new `Layer` instance.
-    pub fn new(
+    /// A new `Layer` instance with weights and biases in `[0, 1)`.
+    pub fn new<R: Rng + ?Sized>(
         input_size: usize,
         output_size: usize,
         activation_function: ActivationFunction,
+        rng: &mut R,
     ) -> Self {
-        let nodes = (0..output_size).map(|_| Node::new(input_size)).collect();
+        let mut weights = Vec::with_capacity(input_size * output_size);
+        let mut biases = Vec::with_capacity(output_size);
+        // The weights and then the bias of every output, in the order the
+        // per-node layers drew them, so seeds give the same networks as before.
+        for _ in 0..output_size {
+            weights.extend((0..input_size).map(|_| rng.gen::<f64>()));
+            biases.push(rng.gen::<f64>());
+        }
+        Self::from_weights(input_size, weights, biases, activation_function)
+    }
 
+    /// Creates a layer from a row-major matrix of weights with a row per output.
+    ///
+    /// # Panics
+    ///
+    /// If there is not one row of `input_size` weights for every bias.
+    pub fn from_weights(
+        input_size: usize,
+        weights: Vec<f64>,
+        biases: Vec<f64>,
+        activation_function: ActivationFunction,
+    ) -> Self {
+        assert!(input_size > 0);
+        assert!(weights.len() == input_size * biases.len());
         Layer {
-            nodes,
+            input_size,
+            weights,
+            biases,
             activation_function,
         }
     }
 
+    /// Returns the number of inputs of the layer.
+    pub fn input_size(&self) -> usize {
+        self.input_size
+    }
+
+    /// Returns the number of outputs of the layer.
+    pub fn output_size(&self) -> usize {
+        self.biases.len()
+    }
+
+    /// Returns the weights of the given output.
+    pub fn row(&self, output: usize) -> &[f64] {
+        &self.weights[output * self.input_size..(output + 1) * self.input_size]
+    }
+
     /// Performs a forward pass through the layer by
-    /// computing the output of each node.
+    /// computing the activated weighted sum of each output.
     ///
     /// # Arguments
     ///
-    /// * `inputs` - A vector of input values to the layer.
+    /// * `inputs` - A slice of input values to the layer.
     ///
     /// # Returns
     ///
     /// A vector of output values from the layer.
-    pub fn forward(&self, inputs: Vec<f64>) -> Vec<f64> {
-        self.nodes
-            .iter()
-            .map(|node| {
-                let sum = node.forward(&inputs);
-                self.activation_function.activate(sum)
-            })
+    pub fn forward(&self, inputs: &[f64]) -> Vec<f64> {
+        let mut outputs = Vec::with_capacity(self.output_size());
+        self.forward_into(inputs, &mut outputs);
+        outputs
+    }
+
+    /// Performs a forward pass for a batch of inputs stored one after another,
+    /// replacing the contents of `outputs` with the outputs stored the same way.
+    ///
+    /// # Panics
+    ///
+    /// If the length of `inputs` is not a multiple of the input size.
+    pub fn forward_into(&self, inputs: &[f64], outputs: &mut Vec<f64>) {
+        assert!(inputs.len().is_multiple_of(self.input_size));
+        outputs.clear();
+        for input in inputs.chunks_exact(self.input_size) {
+            outputs.extend(
+                self.weights
+                    .chunks_exact(self.input_size)
+                    .zip(&self.biases)
+                    .map(|(row, bias)| self.activation_function.activate(dot(row, input) + bias)),
+            );
+        }
+    }
+
+    /// Computes the weighted sums of the outputs, before the activation function.
+    pub fn sums(&self, inputs: &[f64]) -> Vec<f64> {
+        assert!(inputs.len() == self.input_size);
+        self.weights
+            .chunks_exact(self.input_size)
+            .zip(&self.biases)
+            .map(|(row, bias)| dot(row, inputs) + bias)
             .collect()
     }
 
-    /// Updates the nodes in the layer by random changes.
+    /// Performs a backward pass through the layer.
+    ///
+    /// # Arguments
+    ///
+    /// * `inputs` - The inputs of the forward pass.
+    /// * `sums` - The weighted sums of the forward pass, see [`Layer::sums`].
+    /// * `output_gradient` - The gradient of the loss with respect to the outputs of the layer.
+    ///
+    /// # Returns
+    ///
+    /// The gradient of the loss with respect to the inputs of the layer,
+    /// and the gradients of its weights and biases.
+    pub fn backward(
+        &self,
+        inputs: &[f64],
+        sums: &[f64],
+        output_gradient: &[f64],
+    ) -> (Vec<f64>, Gradients) {
+        let deltas: Vec<f64> = sums
+            .iter()
+            .zip(output_gradient)
+            .map(|(&sum, gradient)| gradient * self.activation_function.derivative(sum))
+            .collect();
+
+        let mut input_gradient = vec![0.0; inputs.len()];
+        let mut weights = Vec::with_capacity(self.weights.len());
+        for (row, delta) in self.weights.chunks_exact(self.input_size).zip(&deltas) {
+            for (gradient, weight) in input_gradient.iter_mut().zip(row) {
+                *gradient += weight * delta;
+            }
+            weights.extend(inputs.iter().map(|input| delta * input));
+        }
+
+        let gradients = Gradients {
+            weights,
+            biases: deltas,
+        };
+        (input_gradient, gradients)
+    }
+
+    /// Updates the weights and biases of the layer by random changes.
     ///
     /// # Arguments
     ///
     /// * `rate` - The probability of each weight (and the bias) being updated.
     /// * `variation` - The variation at which the weights/parameters change.
-    pub fn update(&mut self, rate: f64, variation: f64) {
-        for node in &mut self.nodes {
-            node.update(rate, variation);
+    /// * `rng` - The random number generator used for the changes.
+    pub fn update<R: Rng + ?Sized>(&mut self, rate: f64, variation: f64, rng: &mut R) {
+        let rows = self.weights.chunks_exact_mut(self.input_size);
+        for (row, bias) in rows.zip(&mut self.biases) {
+            for parameter in row.iter_mut().chain([bias]) {
+                if rng.gen::<f64>() < rate {
+                    *parameter += rng.gen_range(-variation..variation);
+                }
+            }
         }
     }
 }
@@ -72,40 +304,41 @@ impl Layer {
 #[cfg(test)]
 mod tests {
     use super::super::activation::ActivationFunction;
-    use super::super::node::Node;
     use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
 
     #[test]
     fn test_new_layer() {
         let input_size = 2;
         let output_size = 3;
         let activation_function = ActivationFunction::Sigmoid;
-        let layer = Layer::new(input_size, output_size, activation_function);
+        let layer = Layer::new(
+            input_size,
+            output_size,
+            activation_function,
+            &mut rand::thread_rng(),
+        );
 
-        assert_eq!(layer.nodes.len(), output_size);
-        for node in &layer.nodes {
-            assert_eq!(node.weights.len(), input_size);
+        assert_eq!(layer.input_size(), input_size);
+        assert_eq!(layer.output_size(), output_size);
+        assert_eq!(layer.weights.len(), input_size * output_size);
+        for &weight in layer.weights.iter().chain(&layer.biases) {
+            assert!((0.0..=1.0).contains(&weight), "Weight out of range");
         }
     }
 
     #[test]
     fn test_forward() {
-        let layer = Layer {
-            nodes: vec![
-                Node {
-                    weights: vec![0.5, 0.5],
-                    bias: 0.0,
-                },
-                Node {
-                    weights: vec![0.3, 0.3],
-                    bias: 0.0,
-                },
-            ],
-            activation_function: ActivationFunction::Sigmoid,
-        };
+        let layer = Layer::from_weights(
+            2,
+            vec![0.5, 0.5, 0.3, 0.3],
+            vec![0.0, 0.0],
+            ActivationFunction::Sigmoid,
+        );
 
         let inputs = vec![1.0, 1.0];
-        let outputs = layer.forward(inputs);
+        let outputs = layer.forward(&inputs);
 
         let expected_output_1 = 1.0 / (1.0 + (-1.0f64).exp()); // sigmoid(0.5 * 1 + 0.5 * 1 + 0)
         let expected_output_2 = 1.0 / (1.0 + (-0.6f64).exp()); // sigmoid(0.3 * 1 + 0.3 * 1 + 0)
@@ -113,4 +346,133 @@ mod tests {
         assert!((outputs[0] - expected_output_1).abs() < 1e-6);
         assert!((outputs[1] - expected_output_2).abs() < 1e-6);
     }
+
+    #[test]
+    #[should_panic(expected = "assertion failed")]
+    fn test_forward_panic_on_mismatched_inputs() {
+        let layer = Layer::from_weights(2, vec![0.5, 0.5], vec![1.0], ActivationFunction::None);
+        layer.forward(&[1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_forward_batch() {
+        let mut rng = SplitMix64::seed_from_u64(0);
+        // Long enough rows to cover both the four lanes and the remainder of the dot product.
+        let layer = Layer::new(7, 3, ActivationFunction::Tanh, &mut rng);
+        let batch: Vec<f64> = (0..14).map(|_| rng.gen_range(-1.0..1.0)).collect();
+
+        let mut outputs = vec![1.0; 10];
+        layer.forward_into(&batch, &mut outputs);
+        assert_eq!(outputs.len(), 6);
+        for (input, output) in batch.chunks(7).zip(outputs.chunks(3)) {
+            assert_eq!(layer.forward(input), output);
+            for (j, &output) in output.iter().enumerate() {
+                let sum = layer
+                    .row(j)
+                    .iter()
+                    .zip(input)
+                    .map(|(w, x)| w * x)
+                    .sum::<f64>();
+                assert!((output - (sum + layer.biases[j]).tanh()).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn test_backward() {
+        let layer = Layer::from_weights(
+            2,
+            vec![0.5, -0.2, 0.3, 0.8],
+            vec![0.1, -0.4],
+            ActivationFunction::Tanh,
+        );
+        let inputs = [0.7, -1.2];
+        // The loss is the sum of the outputs, so every output gradient is 1.
+        let loss = |layer: &Layer, inputs: &[f64]| layer.forward(inputs).iter().sum::<f64>();
+        let (input_gradient, gradients) =
+            layer.backward(&inputs, &layer.sums(&inputs), &[1.0, 1.0]);
+
+        let h = 1e-6;
+        for k in 0..inputs.len() {
+            let mut shifted = inputs;
+            shifted[k] += h;
+            let numeric = (loss(&layer, &shifted) - loss(&layer, &inputs)) / h;
+            assert!((input_gradient[k] - numeric).abs() < 1e-4);
+        }
+        for k in 0..layer.weights.len() {
+            let mut shifted = layer.clone();
+            shifted.weights[k] += h;
+            let numeric = (loss(&shifted, &inputs) - loss(&layer, &inputs)) / h;
+            assert!((gradients.weights[k] - numeric).abs() < 1e-4);
+        }
+        for j in 0..layer.biases.len() {
+            let mut shifted = layer.clone();
+            shifted.biases[j] += h;
+            let numeric = (loss(&shifted, &inputs) - loss(&layer, &inputs)) / h;
+            assert!((gradients.biases[j] - numeric).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_update_with_no_changes() {
+        let mut layer = Layer::new(5, 2, ActivationFunction::None, &mut rand::thread_rng());
+        let original = layer.clone();
+
+        // Use rate 0 to ensure no updates
+        layer.update(0.0, 1.0, &mut rand::thread_rng());
+
+        assert_eq!(original, layer);
+    }
+
+    #[test]
+    fn test_update_with_all_changes() {
+        let mut layer = Layer::new(5, 2, ActivationFunction::None, &mut rand::thread_rng());
+        let original = layer.clone();
+
+        // Use rate 1 to ensure all weights and biases are updated, within the variation
+        let variation = 0.5;
+        layer.update(1.0, variation, &mut rand::thread_rng());
+
+        let parameters = |layer: &Layer| {
+            let mut parameters = layer.weights.clone();
+            parameters.extend(&layer.biases);
+            parameters
+        };
+        for (original, updated) in parameters(&original).iter().zip(parameters(&layer)) {
+            let diff = (original - updated).abs();
+            assert!(diff > f64::EPSILON && diff <= variation);
+        }
+    }
+
+    #[test]
+    fn test_seeded_layer() {
+        let layer = |seed| {
+            let mut rng = SplitMix64::seed_from_u64(seed);
+            let mut layer = Layer::new(5, 3, ActivationFunction::ReLU, &mut rng);
+            layer.update(0.5, 0.1, &mut rng);
+            layer
+        };
+        assert_eq!(layer(3), layer(3));
+    }
+
+    #[test]
+    fn loads_both_formats() {
+        let matrix = Layer::from_weights(
+            2,
+            vec![0.5, -0.2, 0.3, 0.8],
+            vec![0.1, -0.4],
+            ActivationFunction::ReLU,
+        );
+        let json = serde_json::to_string(&matrix).unwrap();
+        assert_eq!(serde_json::from_str::<Layer>(&json).unwrap(), matrix);
+
+        let nodes = r#"{"nodes":[{"weights":[0.5,-0.2],"bias":0.1},{"weights":[0.3,0.8],"bias":-0.4}],"activation_function":"ReLU"}"#;
+        assert_eq!(serde_json::from_str::<Layer>(nodes).unwrap(), matrix);
+
+        let ragged = r#"{"nodes":[{"weights":[0.5,-0.2],"bias":0.1},{"weights":[0.3],"bias":-0.4}],"activation_function":"ReLU"}"#;
+        assert!(serde_json::from_str::<Layer>(ragged).is_err());
+        let short =
+            r#"{"input_size":2,"weights":[0.5],"biases":[0.1],"activation_function":"ReLU"}"#;
+        assert!(serde_json::from_str::<Layer>(short).is_err());
+    }
 }
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+
+/// Computes the softmax of the given values.
+pub fn softmax(values: &[f64]) -> Vec<f64> {
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<f64> = values.iter().map(|x| (x - max).exp()).collect();
+    let sum: f64 = exps.iter().sum();
+    exps.iter().map(|x| x / sum).collect()
+}
+
+/// Enumeration of possible loss functions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Loss {
+    /// The mean of the squared differences between outputs and targets.
+    MeanSquaredError,
+    /// The cross-entropy between the targets and the softmax of the outputs,
+    /// so the outputs are treated as logits.
+    CrossEntropy,
+}
+
+impl Loss {
+    /// Computes the loss of the outputs for the given targets.
+    pub fn loss(&self, outputs: &[f64], targets: &[f64]) -> f64 {
+        assert!(outputs.len() == targets.len());
+        match self {
+            Loss::MeanSquaredError => {
+                outputs
+                    .iter()
+                    .zip(targets)
+                    .map(|(o, t)| (o - t).powi(2))
+                    .sum::<f64>()
+                    / outputs.len() as f64
+            }
+            Loss::CrossEntropy => -softmax(outputs)
+                .iter()
+                .zip(targets)
+                .map(|(p, t)| t * p.max(f64::MIN_POSITIVE).ln())
+                .sum::<f64>(),
+        }
+    }
+
+    /// Computes the gradient of the loss with respect to the outputs.
+    pub fn gradient(&self, outputs: &[f64], targets: &[f64]) -> Vec<f64> {
+        assert!(outputs.len() == targets.len());
+        match self {
+            Loss::MeanSquaredError => outputs
+                .iter()
+                .zip(targets)
+                .map(|(o, t)| 2.0 * (o - t) / outputs.len() as f64)
+                .collect(),
+            // Assumes that the targets sum to 1.
+            Loss::CrossEntropy => softmax(outputs)
+                .iter()
+                .zip(targets)
+                .map(|(p, t)| p - t)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_softmax() {
+        let probabilities = softmax(&[1.0, 2.0, 3.0]);
+        assert!((probabilities.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!(probabilities[0] < probabilities[1] && probabilities[1] < probabilities[2]);
+        // Large values do not overflow.
+        assert_eq!(softmax(&[1000.0, 1000.0]), vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_losses() {
+        assert_eq!(Loss::MeanSquaredError.loss(&[1.0, 3.0], &[1.0, 1.0]), 2.0);
+        let loss = Loss::CrossEntropy.loss(&[0.0, 0.0], &[1.0, 0.0]);
+        assert!((loss - 2f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gradients_match_finite_differences() {
+        let outputs = [0.3, -1.2, 2.0];
+        let targets = [0.0, 1.0, 0.0];
+        let h = 1e-6;
+        for loss in [Loss::MeanSquaredError, Loss::CrossEntropy] {
+            let gradient = loss.gradient(&outputs, &targets);
+            for k in 0..outputs.len() {
+                let mut shifted = outputs;
+                shifted[k] += h;
+                let numeric = (loss.loss(&shifted, &targets) - loss.loss(&outputs, &targets)) / h;
+                assert!((gradient[k] - numeric).abs() < 1e-4);
+            }
+        }
+    }
+}
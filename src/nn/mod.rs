@@ -1,17 +1,29 @@
 pub mod activation;
+pub mod binary;
+pub mod crossover;
+pub mod encoding;
 mod layer;
-mod node;
-use std::fs::File;
-use std::io::{self};
+pub mod loss;
+pub mod model;
+pub mod optimizer;
 
+use crate::game::{Actions, Game};
 use activation::ActivationFunction;
-use layer::Layer;
+use encoding::InputEncoding;
+pub use layer::{Gradients, Layer};
+use loss::Loss;
+use optimizer::Optimizer;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use strum::EnumCount;
 
 /// A neural network consisting of multiple layers.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct NeuralNetwork {
     pub layers: Vec<Layer>,
+    /// How boards are turned into inputs, raw exponents for models saved without one.
+    #[serde(default)]
+    pub encoding: InputEncoding,
 }
 
 impl NeuralNetwork {
@@ -22,12 +34,17 @@ impl NeuralNetwork {
     ///
     /// * `layer_sizes` - A slice of the sizes of each layer.
     /// * `activation_functions` - A slice of activation
-    ///    functions for each layer.
+    ///   functions for each layer.
+    /// * `rng` - The random number generator used to initialise the weights.
     ///
     /// # Returns
     ///
     /// A new `NeuralNetwork` instance.
-    pub fn new(layer_sizes: &[usize], activation_functions: &[ActivationFunction]) -> Self {
+    pub fn new<R: Rng + ?Sized>(
+        layer_sizes: &[usize],
+        activation_functions: &[ActivationFunction],
+        rng: &mut R,
+    ) -> Self {
         assert!(layer_sizes.len() == activation_functions.len() + 1);
 
         let mut layers = Vec::new();
@@ -37,10 +54,50 @@ impl NeuralNetwork {
                 layer_sizes[i],
                 layer_sizes[i + 1],
                 activation_functions[i],
+                rng,
             ));
         }
 
-        NeuralNetwork { layers }
+        NeuralNetwork {
+            layers,
+            encoding: InputEncoding::default(),
+        }
+    }
+
+    /// Sets how boards are turned into the inputs of the network.
+    pub fn with_encoding(mut self, encoding: InputEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Returns whether the network takes the encoding of boards of `size` rows and columns
+    /// and has one output per move.
+    pub fn supports(&self, size: usize) -> bool {
+        self.input_size() == self.encoding.input_size(size) && self.output_size() == Actions::COUNT
+    }
+
+    /// Computes the outputs of the network for the board of `game`,
+    /// encoded with the encoding of the network.
+    pub fn predict(&self, game: &Game) -> Vec<f64> {
+        self.forward(&self.encoding.encode(game))
+    }
+
+    /// Returns the valid move with the highest output, or `None` if the game is over.
+    pub fn best_move(&self, game: &Game) -> Option<Actions> {
+        let output = self.predict(game);
+        game.valid_moves()
+            .into_iter()
+            .max_by(|&x, &y| output[x as usize].total_cmp(&output[y as usize]))
+    }
+
+    /// Computes the outputs of the network for every game in one batch,
+    /// with `output_size` outputs per game.
+    pub fn predict_batch(&self, games: &[Game]) -> Vec<f64> {
+        let mut inputs = Vec::with_capacity(games.len() * self.input_size());
+        for game in games {
+            self.encoding.encode_into(game, &mut inputs);
+        }
+        self.forward_batch(&inputs)
     }
 
     /// Performs a forward pass through the neural network
@@ -48,59 +105,137 @@ impl NeuralNetwork {
     ///
     /// # Arguments
     ///
-    /// * `inputs` - A vector of input values to the neural network.
+    /// * `inputs` - A slice of input values to the neural network.
     ///
     /// # Returns
     ///
     /// A vector of output values from the neural network.
-    pub fn forward(&self, inputs: Vec<f64>) -> Vec<f64> {
-        let mut output = inputs;
-        for layer in &self.layers {
-            output = layer.forward(output);
-        }
-        output
+    pub fn forward(&self, inputs: &[f64]) -> Vec<f64> {
+        self.forward_batch(inputs)
     }
 
-    /// Updates the layers in the neural network by random changes.
+    /// Performs a forward pass for a batch of inputs stored one after another,
+    /// which is faster than a pass per input since every layer goes through
+    /// its weights once for the whole batch.
     ///
     /// # Arguments
     ///
-    /// * `rate` - The probability of each weight (and the bias) being updated.
-    /// * `variation` - The variation at which the weights/parameters change.
-    pub fn update(&mut self, rate: f64, variation: f64) {
-        for layer in &mut self.layers {
-            layer.update(rate, variation);
+    /// * `inputs` - The input values of every sample, `input_size` per sample.
+    ///
+    /// # Returns
+    ///
+    /// The output values of every sample, `output_size` per sample.
+    pub fn forward_batch(&self, inputs: &[f64]) -> Vec<f64> {
+        let Some((first, rest)) = self.layers.split_first() else {
+            return inputs.to_vec();
+        };
+        // Two buffers that take turns as the inputs and outputs of the layers.
+        let mut output = Vec::new();
+        let mut buffer = Vec::new();
+        first.forward_into(inputs, &mut output);
+        for layer in rest {
+            layer.forward_into(&output, &mut buffer);
+            std::mem::swap(&mut output, &mut buffer);
         }
+        output
     }
 
-    /// Saves the neural network to a file in JSON format.
+    /// Computes the loss of the network on a single sample
+    /// and its gradients by backpropagation.
     ///
     /// # Arguments
     ///
-    /// * `filename` - The name of the file to save the neural network to.
+    /// * `inputs` - The input values of the sample.
+    /// * `targets` - The expected output values of the sample.
+    /// * `loss` - The loss function comparing the outputs with the targets.
     ///
     /// # Returns
     ///
-    /// A `Result` indicating success or failure.
-    pub fn save(&self, filename: &str) -> io::Result<()> {
-        let file = File::create(filename)?;
-        serde_json::to_writer(file, &self)?;
-        Ok(())
+    /// The loss, and the gradients of the weights and biases of every layer.
+    pub fn gradients(&self, inputs: &[f64], targets: &[f64], loss: Loss) -> (f64, Vec<Gradients>) {
+        // The inputs and weighted sums of every layer, kept for the backward pass.
+        let mut activations = vec![inputs.to_vec()];
+        let mut sums = Vec::with_capacity(self.layers.len());
+        for layer in &self.layers {
+            let layer_sums = layer.sums(activations.last().unwrap());
+            let outputs = layer_sums
+                .iter()
+                .map(|&sum| layer.activation_function.activate(sum))
+                .collect();
+            sums.push(layer_sums);
+            activations.push(outputs);
+        }
+
+        let outputs = activations.pop().unwrap();
+        let mut gradient = loss.gradient(&outputs, targets);
+        let mut gradients = Vec::with_capacity(self.layers.len());
+        for (layer, (inputs, sums)) in self.layers.iter().zip(activations.iter().zip(&sums)).rev() {
+            let (input_gradient, layer_gradients) = layer.backward(inputs, sums, &gradient);
+            gradient = input_gradient;
+            gradients.push(layer_gradients);
+        }
+        gradients.reverse();
+        (loss.loss(&outputs, targets), gradients)
     }
 
-    /// Loads a neural network from a file in JSON format.
+    /// Trains the network for one pass over the samples, in order,
+    /// taking an optimizer step with the mean gradients of every batch.
     ///
     /// # Arguments
     ///
-    /// * `filename` - The name of the file to load the neural network from.
+    /// * `samples` - Pairs of input values and expected output values.
+    /// * `batch_size` - The number of samples per step.
+    /// * `loss` - The loss function to minimise.
+    /// * `optimizer` - The optimizer that updates the weights and biases.
     ///
     /// # Returns
     ///
-    /// A `Result` containing the loaded `NeuralNetwork` or an error.
-    pub fn load(filename: &str) -> io::Result<Self> {
-        let file = File::open(filename)?;
-        let network = serde_json::from_reader(file)?;
-        Ok(network)
+    /// The mean loss over the samples, before their steps.
+    pub fn fit(
+        &mut self,
+        samples: &[(Vec<f64>, Vec<f64>)],
+        batch_size: usize,
+        loss: Loss,
+        optimizer: &mut Optimizer,
+    ) -> f64 {
+        assert!(batch_size > 0);
+        let mut total = 0.0;
+        for batch in samples.chunks(batch_size) {
+            let mut sum: Vec<Gradients> = self.layers.iter().map(Gradients::zeros).collect();
+            let scale = 1.0 / batch.len() as f64;
+            for (inputs, targets) in batch {
+                let (sample_loss, gradients) = self.gradients(inputs, targets, loss);
+                total += sample_loss;
+                for (sum, gradients) in sum.iter_mut().zip(&gradients) {
+                    sum.add_scaled(gradients, scale);
+                }
+            }
+            optimizer.step(self, &sum);
+        }
+        total / samples.len().max(1) as f64
+    }
+
+    /// Returns the number of inputs the neural network expects.
+    pub fn input_size(&self) -> usize {
+        self.layers.first().map_or(0, |layer| layer.input_size())
+    }
+
+    /// Returns the number of outputs of the neural network.
+    pub fn output_size(&self) -> usize {
+        self.layers.last().map_or(0, |layer| layer.output_size())
+    }
+
+    /// Updates the layers in the neural network by random changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `rate` - The probability of each weight (and the bias) being updated.
+    /// * `variation` - The variation at which the weights/parameters change.
+    /// * `rng` - The random number generator used for the changes.
+    pub fn update<R: Rng + ?Sized>(&mut self, rate: f64, variation: f64, rng: &mut R) {
+        for layer in &mut self.layers {
+            layer.update(rate, variation, rng);
+        }
     }
 }
 
@@ -108,6 +243,8 @@ impl NeuralNetwork {
 mod tests {
     use super::activation::ActivationFunction;
     use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
     use std::fs;
 
     #[test]
@@ -115,11 +252,13 @@ mod tests {
         let layer_sizes = &[2, 3, 1];
         let activation_functions = vec![ActivationFunction::Sigmoid, ActivationFunction::Sigmoid];
 
-        let nn = NeuralNetwork::new(layer_sizes, &activation_functions);
+        let nn = NeuralNetwork::new(layer_sizes, &activation_functions, &mut rand::thread_rng());
 
         assert_eq!(nn.layers.len(), 2);
-        assert_eq!(nn.layers[0].nodes.len(), 3);
-        assert_eq!(nn.layers[1].nodes.len(), 1);
+        assert_eq!(nn.input_size(), 2);
+        assert_eq!(nn.output_size(), 1);
+        assert_eq!(nn.layers[0].output_size(), 3);
+        assert_eq!(nn.layers[1].output_size(), 1);
     }
 
     #[test]
@@ -127,18 +266,17 @@ mod tests {
         let layer_sizes = &[2, 2, 1];
         let activation_functions = vec![ActivationFunction::Sigmoid, ActivationFunction::Sigmoid];
 
-        let mut nn = NeuralNetwork::new(layer_sizes, &activation_functions);
+        let mut nn =
+            NeuralNetwork::new(layer_sizes, &activation_functions, &mut rand::thread_rng());
 
         // Manually setting weights and biases for deterministic testing
-        nn.layers[0].nodes[0].weights = vec![0.5, 0.5];
-        nn.layers[0].nodes[0].bias = 0.0;
-        nn.layers[0].nodes[1].weights = vec![0.3, 0.3];
-        nn.layers[0].nodes[1].bias = 0.0;
-        nn.layers[1].nodes[0].weights = vec![0.2, 0.2];
-        nn.layers[1].nodes[0].bias = 0.0;
+        nn.layers[0].weights = vec![0.5, 0.5, 0.3, 0.3];
+        nn.layers[0].biases = vec![0.0, 0.0];
+        nn.layers[1].weights = vec![0.2, 0.2];
+        nn.layers[1].biases = vec![0.0];
 
         let inputs = vec![1.0, 1.0];
-        let outputs = nn.forward(inputs);
+        let outputs = nn.forward(&inputs);
 
         let expected_output_0 = 1.0 / (1.0 + (-1.0f64).exp()); // sigmoid(0.5 * 1 + 0.5 * 1 + 0)
         let expected_output_1 = 1.0 / (1.0 + (-0.6f64).exp()); // sigmoid(0.3 * 1 + 0.3 * 1 + 0)
@@ -151,9 +289,10 @@ mod tests {
 
     #[test]
     fn test_save_and_load() {
-        let layer_sizes = vec![2, 3, 1];
+        let layer_sizes = vec![2, 3, 4];
         let activation_functions = vec![ActivationFunction::ReLU, ActivationFunction::Sigmoid];
-        let network = NeuralNetwork::new(&layer_sizes, &activation_functions);
+        let network =
+            NeuralNetwork::new(&layer_sizes, &activation_functions, &mut rand::thread_rng());
 
         let filename = "test_model.json";
         network.save(filename).expect("Failed to save the network");
@@ -161,8 +300,128 @@ mod tests {
         let loaded_network = NeuralNetwork::load(filename).expect("Failed to load the network");
 
         assert_eq!(network.layers.len(), loaded_network.layers.len());
+        for (layer, loaded) in network.layers.iter().zip(&loaded_network.layers) {
+            // JSON keeps the weights up to the last bit or so.
+            let mut weights = layer.weights.iter().zip(&loaded.weights);
+            assert!(weights.all(|(w, l)| (w - l).abs() < 1e-12));
+        }
 
         // Clean up
         fs::remove_file(filename).expect("Failed to remove test file");
     }
+
+    #[test]
+    fn test_load_node_format() {
+        // The bundled model was saved as a list of nodes per layer.
+        let nn = NeuralNetwork::load("128.json").expect("Failed to load the model");
+        assert_eq!(nn.input_size(), 16);
+        assert_eq!(nn.output_size(), 4);
+        assert_eq!(nn.layers[0].output_size(), 128);
+        assert_eq!(nn.layers[1].input_size(), 128);
+        assert_eq!(nn.encoding, InputEncoding::Raw);
+    }
+
+    #[test]
+    fn test_encoding_is_saved() {
+        let mut rng = SplitMix64::seed_from_u64(3);
+        let encoding = InputEncoding::OneHot;
+        let nn = NeuralNetwork::new(
+            &[encoding.input_size(3), 4, 4],
+            &[ActivationFunction::ReLU, ActivationFunction::None],
+            &mut rng,
+        )
+        .with_encoding(encoding);
+        assert!(nn.supports(3) && !nn.supports(4));
+
+        let filename = "test_encoding_model.json";
+        nn.save(filename).expect("Failed to save the network");
+        let loaded = NeuralNetwork::load(filename).expect("Failed to load the network");
+        fs::remove_file(filename).expect("Failed to remove test file");
+        assert_eq!(loaded.encoding, encoding);
+
+        let games = [Game::with_size(3, 1), Game::with_size(3, 2)];
+        let outputs = loaded.predict_batch(&games);
+        assert_eq!(&outputs[..4], loaded.predict(&games[0]));
+        assert_eq!(&outputs[4..], loaded.predict(&games[1]));
+    }
+
+    #[test]
+    fn test_forward_batch() {
+        let mut rng = SplitMix64::seed_from_u64(2);
+        let nn = NeuralNetwork::new(
+            &[5, 6, 3],
+            &[ActivationFunction::ReLU, ActivationFunction::Sigmoid],
+            &mut rng,
+        );
+        let batch: Vec<f64> = (0..20).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let outputs = nn.forward_batch(&batch);
+        assert_eq!(outputs.len(), 12);
+        for (input, output) in batch.chunks(5).zip(outputs.chunks(3)) {
+            assert_eq!(nn.forward(input), output);
+        }
+    }
+
+    #[test]
+    fn test_gradients() {
+        let mut rng = SplitMix64::seed_from_u64(0);
+        let nn = NeuralNetwork::new(
+            &[3, 4, 2],
+            &[ActivationFunction::Tanh, ActivationFunction::Sigmoid],
+            &mut rng,
+        );
+        let inputs = [0.2, -0.7, 1.1];
+        let targets = [1.0, 0.0];
+        let loss = Loss::MeanSquaredError;
+        let (value, gradients) = nn.gradients(&inputs, &targets, loss);
+        assert_eq!(value, loss.loss(&nn.forward(&inputs), &targets));
+
+        // Every gradient matches the change of the loss when moving its weight.
+        let h = 1e-6;
+        for (l, layer) in nn.layers.iter().enumerate() {
+            for k in 0..layer.weights.len() {
+                let mut shifted = nn.clone();
+                shifted.layers[l].weights[k] += h;
+                let numeric = (shifted.gradients(&inputs, &targets, loss).0 - value) / h;
+                assert!((gradients[l].weights[k] - numeric).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fit_classification() {
+        // The class is whether the first input is larger than the second.
+        let mut rng = SplitMix64::seed_from_u64(1);
+        let samples: Vec<(Vec<f64>, Vec<f64>)> = (0..64)
+            .map(|_| {
+                let (x, y) = (rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+                let target = if x > y {
+                    vec![1.0, 0.0]
+                } else {
+                    vec![0.0, 1.0]
+                };
+                (vec![x, y], target)
+            })
+            .collect();
+        let mut nn = NeuralNetwork::new(
+            &[2, 8, 2],
+            &[ActivationFunction::Tanh, ActivationFunction::None],
+            &mut rng,
+        );
+        let mut optimizer = Optimizer::adam(0.01);
+        let first = nn.fit(&samples, 8, Loss::CrossEntropy, &mut optimizer);
+        for _ in 0..200 {
+            nn.fit(&samples, 8, Loss::CrossEntropy, &mut optimizer);
+        }
+        let last = nn.fit(&samples, 8, Loss::CrossEntropy, &mut optimizer);
+        assert!(last < first / 4.0, "{first} -> {last}");
+
+        let correct = samples
+            .iter()
+            .filter(|(inputs, targets)| {
+                let outputs = nn.forward(inputs);
+                (outputs[0] > outputs[1]) == (targets[0] > targets[1])
+            })
+            .count();
+        assert!(correct >= 60, "{correct} of 64");
+    }
 }
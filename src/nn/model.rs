@@ -0,0 +1,453 @@
+use super::binary::MAGIC;
+use super::NeuralNetwork;
+use crate::game::Actions;
+use crate::time::now_ms;
+use strum::EnumCount;
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+
+/// The version of the model files written by [`NeuralNetwork::save`].
+/// Files without a version hold just the network and are read as version 0.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Where a model comes from, saved next to its network.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    /// When the model was saved, in milliseconds since the unix epoch.
+    pub created_at_ms: Option<u64>,
+    /// The evolution step of the population the model was taken from.
+    pub evolution_step: Option<usize>,
+    /// The average score of the model in its last evaluation.
+    pub average_score: Option<f64>,
+    /// The settings of the training run, by name.
+    pub hyperparameters: BTreeMap<String, f64>,
+    /// The seeds of the games of the last evaluation, to replay them.
+    pub game_seeds: Vec<u64>,
+}
+
+impl Metadata {
+    /// Returns the metadata with the creation time set to now, unless it is already set,
+    /// so converting a model keeps the time it was created.
+    pub(super) fn stamped(&self) -> Self {
+        Metadata {
+            created_at_ms: self.created_at_ms.or(Some(now_ms() as u64)),
+            ..self.clone()
+        }
+    }
+}
+
+/// The envelope of a model file, with the architecture repeated
+/// in front of the weights so it can be read and checked without them.
+#[derive(Serialize, Deserialize)]
+struct ModelFile<N> {
+    version: u32,
+    metadata: Metadata,
+    layer_sizes: Vec<usize>,
+    network: N,
+}
+
+/// The reasons why a model file cannot be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    Parse(serde_json::Error),
+    /// The file is neither a binary model nor UTF-8 text.
+    Utf8(std::str::Utf8Error),
+    UnsupportedVersion(u32),
+    /// The file looks like a binary model, but is not a valid one.
+    InvalidBinary(String),
+    /// The inputs of `layer` do not match the outputs of the layer before it,
+    /// or the size the file gives for it.
+    ShapeMismatch {
+        layer: usize,
+        expected: usize,
+        found: usize,
+    },
+    InputSize {
+        expected: usize,
+        found: usize,
+    },
+    /// The network does not have one output per move, or as many as the file gives.
+    OutputSize {
+        expected: usize,
+        found: usize,
+    },
+    /// The number of layer sizes the file gives does not match the layers.
+    LayerCount {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "{err}"),
+            LoadError::Parse(err) => write!(f, "invalid model file: {err}"),
+            LoadError::Utf8(err) => write!(f, "the model file is not text: {err}"),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported model format version {version}, the latest is {FORMAT_VERSION}"
+            ),
+            LoadError::InvalidBinary(reason) => write!(f, "invalid binary model: {reason}"),
+            LoadError::ShapeMismatch {
+                layer,
+                expected,
+                found,
+            } => write!(f, "layer {layer} has {found} inputs, expected {expected}"),
+            LoadError::InputSize { expected, found } => {
+                write!(f, "the model has {found} inputs, expected {expected}")
+            }
+            LoadError::OutputSize { expected, found } => {
+                write!(f, "the model has {found} outputs, expected {expected}")
+            }
+            LoadError::LayerCount { expected, found } => {
+                write!(f, "the model has {found} layer sizes, expected {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            LoadError::Parse(err) => Some(err),
+            LoadError::Utf8(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+impl From<std::str::Utf8Error> for LoadError {
+    fn from(err: std::str::Utf8Error) -> Self {
+        LoadError::Utf8(err)
+    }
+}
+
+impl From<serde_json::Error> for LoadError {
+    fn from(err: serde_json::Error) -> Self {
+        LoadError::Parse(err)
+    }
+}
+
+impl NeuralNetwork {
+    /// Returns the number of inputs followed by the number of outputs of every layer.
+    pub fn layer_sizes(&self) -> Vec<usize> {
+        let outputs = self.layers.iter().map(|layer| layer.output_size());
+        self.layers
+            .first()
+            .map(|layer| layer.input_size())
+            .into_iter()
+            .chain(outputs)
+            .collect()
+    }
+
+    /// Checks that every layer takes as many inputs as the layer before it has outputs.
+    pub fn validate(&self) -> Result<(), LoadError> {
+        for (layer, pair) in self.layers.windows(2).enumerate() {
+            if pair[1].input_size() != pair[0].output_size() {
+                return Err(LoadError::ShapeMismatch {
+                    layer: layer + 1,
+                    expected: pair[0].output_size(),
+                    found: pair[1].input_size(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that a network has one output per move,
+    /// as the networks of model files play the game.
+    pub(super) fn check_outputs(outputs: usize) -> Result<(), LoadError> {
+        if outputs != Actions::COUNT {
+            return Err(LoadError::OutputSize {
+                expected: Actions::COUNT,
+                found: outputs,
+            });
+        }
+        Ok(())
+    }
+
+    /// Saves the neural network to a file in JSON format, with empty metadata.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file to save the neural network to.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` indicating success or failure.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        self.save_with_metadata(filename, &Metadata::default())
+    }
+
+    /// Saves the neural network with `metadata` to a file in JSON format,
+    /// setting the creation time to now unless it is already set.
+    pub fn save_with_metadata(&self, filename: &str, metadata: &Metadata) -> io::Result<()> {
+        let file = File::create(filename)?;
+        let model = ModelFile {
+            version: FORMAT_VERSION,
+            metadata: metadata.stamped(),
+            layer_sizes: self.layer_sizes(),
+            network: self,
+        };
+        serde_json::to_writer(file, &model)?;
+        Ok(())
+    }
+
+    /// Loads a neural network from a file in JSON or the binary format.
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The name of the file to load the neural network from.
+    ///
+    /// # Returns
+    ///
+    /// A `Result` containing the loaded `NeuralNetwork` or the reason it could not be loaded.
+    pub fn load(filename: &str) -> Result<Self, LoadError> {
+        Self::load_with_metadata(filename).map(|(network, _)| network)
+    }
+
+    /// Loads a neural network and its metadata from a file in JSON format,
+    /// or in the binary format if the file starts with its [`MAGIC`].
+    /// JSON files of version 0 have no metadata and load with empty metadata.
+    pub fn load_with_metadata(filename: &str) -> Result<(Self, Metadata), LoadError> {
+        Self::decode(&fs::read(filename)?)
+    }
+
+    /// Reads a neural network and its metadata from the contents of a model file,
+    /// in the binary format if they start with its [`MAGIC`] and in JSON otherwise.
+    pub fn decode(bytes: &[u8]) -> Result<(Self, Metadata), LoadError> {
+        if bytes.starts_with(&MAGIC) {
+            return Self::from_bytes(bytes);
+        }
+        Self::from_json(std::str::from_utf8(bytes)?)
+    }
+
+    fn from_json(json: &str) -> Result<(Self, Metadata), LoadError> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let version = match value.get("version") {
+            Some(version) => serde_json::from_value(version.clone())?,
+            None => 0,
+        };
+        let (network, metadata) = match version {
+            0 => (serde_json::from_value::<Self>(value)?, Metadata::default()),
+            FORMAT_VERSION => {
+                let model: ModelFile<Self> = serde_json::from_value(value)?;
+                model.network.check_layer_sizes(&model.layer_sizes)?;
+                (model.network, model.metadata)
+            }
+            version => return Err(LoadError::UnsupportedVersion(version)),
+        };
+        network.validate()?;
+        Self::check_outputs(network.output_size())?;
+        Ok((network, metadata))
+    }
+
+    /// Checks the layers against the sizes given by a model file.
+    fn check_layer_sizes(&self, sizes: &[usize]) -> Result<(), LoadError> {
+        self.validate()?;
+        let actual = self.layer_sizes();
+        let (expected, found) = (sizes.first(), actual.first());
+        if expected != found {
+            return Err(LoadError::InputSize {
+                expected: expected.copied().unwrap_or(0),
+                found: found.copied().unwrap_or(0),
+            });
+        }
+        if sizes.len() != actual.len() {
+            return Err(LoadError::LayerCount {
+                expected: sizes.len(),
+                found: actual.len(),
+            });
+        }
+        let (expected, found) = (sizes.last(), actual.last());
+        if expected != found {
+            return Err(LoadError::OutputSize {
+                expected: expected.copied().unwrap_or(0),
+                found: found.copied().unwrap_or(0),
+            });
+        }
+        for (layer, (&expected, &found)) in sizes.iter().zip(&actual).enumerate().skip(1) {
+            if expected != found {
+                // The size given for the outputs of a layer is the inputs of the next one.
+                return Err(LoadError::ShapeMismatch {
+                    layer,
+                    expected,
+                    found,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::activation::ActivationFunction;
+    use super::super::encoding::InputEncoding;
+    use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
+
+    fn network() -> NeuralNetwork {
+        NeuralNetwork::new(
+            &[3, 4, 4],
+            &[ActivationFunction::ReLU, ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(0),
+        )
+        .with_encoding(InputEncoding::Normalized)
+    }
+
+    fn model_json(network: &NeuralNetwork) -> serde_json::Value {
+        serde_json::to_value(ModelFile {
+            version: FORMAT_VERSION,
+            metadata: Metadata::default(),
+            layer_sizes: network.layer_sizes(),
+            network,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn saves_the_metadata() {
+        let metadata = Metadata {
+            evolution_step: Some(40),
+            average_score: Some(1234.5),
+            game_seeds: vec![u64::MAX, 3],
+            hyperparameters: BTreeMap::from([("mutation_rate".to_string(), 0.1)]),
+            ..Default::default()
+        };
+        let filename = "test_model_metadata.json";
+        network().save_with_metadata(filename, &metadata).unwrap();
+        let (loaded, loaded_metadata) = NeuralNetwork::load_with_metadata(filename).unwrap();
+        fs::remove_file(filename).unwrap();
+
+        assert_eq!(loaded.layer_sizes(), [3, 4, 4]);
+        assert_eq!(loaded.encoding, InputEncoding::Normalized);
+        assert!(loaded_metadata.created_at_ms.is_some());
+        assert_eq!(
+            loaded_metadata,
+            Metadata {
+                created_at_ms: loaded_metadata.created_at_ms,
+                ..metadata
+            }
+        );
+    }
+
+    #[test]
+    fn loads_unversioned_networks() {
+        let json = serde_json::to_string(&network()).unwrap();
+        let (loaded, metadata) = NeuralNetwork::from_json(&json).unwrap();
+        assert_eq!(loaded.layer_sizes(), [3, 4, 4]);
+        assert_eq!(metadata, Metadata::default());
+    }
+
+    #[test]
+    fn load_errors() {
+        let mut json = model_json(&network());
+        json["version"] = 2.into();
+        let result = NeuralNetwork::from_json(&json.to_string());
+        assert!(matches!(result, Err(LoadError::UnsupportedVersion(2))));
+
+        let mut json = model_json(&network());
+        json["layer_sizes"] = serde_json::json!([5, 4, 4]);
+        let result = NeuralNetwork::from_json(&json.to_string());
+        assert!(matches!(
+            result,
+            Err(LoadError::InputSize {
+                expected: 5,
+                found: 3
+            })
+        ));
+
+        let mut json = model_json(&network());
+        json["layer_sizes"] = serde_json::json!([3, 4, 2]);
+        let result = NeuralNetwork::from_json(&json.to_string());
+        assert!(matches!(
+            result,
+            Err(LoadError::OutputSize {
+                expected: 2,
+                found: 4
+            })
+        ));
+
+        let mut json = model_json(&network());
+        json["layer_sizes"] = serde_json::json!([3, 4, 4, 4]);
+        let result = NeuralNetwork::from_json(&json.to_string());
+        assert!(matches!(
+            result,
+            Err(LoadError::LayerCount {
+                expected: 4,
+                found: 3
+            })
+        ));
+
+        // A network with 2 outputs cannot choose between the 4 moves.
+        let narrow = NeuralNetwork::new(
+            &[3, 2],
+            &[ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(1),
+        );
+        let result = NeuralNetwork::from_json(&model_json(&narrow).to_string());
+        assert!(matches!(
+            result,
+            Err(LoadError::OutputSize {
+                expected: 4,
+                found: 2
+            })
+        ));
+
+        let mut json = model_json(&network());
+        json["layer_sizes"] = serde_json::json!([3, 5, 4]);
+        let result = NeuralNetwork::from_json(&json.to_string());
+        assert!(matches!(
+            result,
+            Err(LoadError::ShapeMismatch { layer: 1, .. })
+        ));
+
+        // A second layer that does not take the 4 outputs of the first.
+        let mut broken = network();
+        broken.layers[1] = NeuralNetwork::new(
+            &[5, 4],
+            &[ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(1),
+        )
+        .layers
+        .remove(0);
+        let result = NeuralNetwork::from_json(&model_json(&broken).to_string());
+        assert!(matches!(
+            result,
+            Err(LoadError::ShapeMismatch {
+                layer: 1,
+                expected: 4,
+                found: 5
+            })
+        ));
+
+        assert!(matches!(
+            NeuralNetwork::from_json("{"),
+            Err(LoadError::Parse(_))
+        ));
+        assert!(matches!(
+            NeuralNetwork::decode(&[0xff, 0xfe]),
+            Err(LoadError::Utf8(_))
+        ));
+        assert!(matches!(
+            NeuralNetwork::load("does_not_exist.json"),
+            Err(LoadError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+use super::layer::Gradients;
+use super::NeuralNetwork;
+use serde::{Deserialize, Serialize};
+
+/// How an optimizer turns gradients into changes of the parameters.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Method {
+    /// Plain stochastic gradient descent.
+    Sgd,
+    /// Gradient descent with a velocity that decays by `momentum` every step.
+    Momentum { momentum: f64 },
+    /// Adam, with running averages of the gradients and their squares.
+    Adam {
+        beta1: f64,
+        beta2: f64,
+        epsilon: f64,
+    },
+}
+
+/// Updates the parameters of a neural network from gradients.
+///
+/// The optimizer keeps the running averages of its method, so the same
+/// optimizer should be used for every step of a training run.
+#[derive(Clone, Debug)]
+pub struct Optimizer {
+    pub method: Method,
+    pub learning_rate: f64,
+    step: i32,
+    /// The velocity for momentum, or the average gradients for Adam.
+    first_moment: Vec<Gradients>,
+    /// The average squared gradients for Adam.
+    second_moment: Vec<Gradients>,
+}
+
+impl Optimizer {
+    pub fn new(method: Method, learning_rate: f64) -> Self {
+        Optimizer {
+            method,
+            learning_rate,
+            step: 0,
+            first_moment: vec![],
+            second_moment: vec![],
+        }
+    }
+
+    pub fn sgd(learning_rate: f64) -> Self {
+        Self::new(Method::Sgd, learning_rate)
+    }
+
+    pub fn momentum(learning_rate: f64, momentum: f64) -> Self {
+        Self::new(Method::Momentum { momentum }, learning_rate)
+    }
+
+    /// Adam with the usual averaging rates.
+    pub fn adam(learning_rate: f64) -> Self {
+        Self::new(
+            Method::Adam {
+                beta1: 0.9,
+                beta2: 0.999,
+                epsilon: 1e-8,
+            },
+            learning_rate,
+        )
+    }
+
+    /// Changes the weights and biases of `nn` against the gradients,
+    /// which have one entry per layer.
+    pub fn step(&mut self, nn: &mut NeuralNetwork, gradients: &[Gradients]) {
+        assert!(gradients.len() == nn.layers.len());
+        if self.first_moment.is_empty() {
+            self.first_moment = nn.layers.iter().map(Gradients::zeros).collect();
+            self.second_moment = self.first_moment.clone();
+        }
+        self.step += 1;
+
+        for (l, (layer, gradients)) in nn.layers.iter_mut().zip(gradients).enumerate() {
+            let first = &mut self.first_moment[l];
+            let second = &mut self.second_moment[l];
+            let parameters = layer.weights.iter_mut().chain(&mut layer.biases);
+            let gradients = gradients.weights.iter().chain(&gradients.biases);
+            let firsts = first.weights.iter_mut().chain(&mut first.biases);
+            let seconds = second.weights.iter_mut().chain(&mut second.biases);
+            for (((parameter, gradient), first), second) in
+                parameters.zip(gradients).zip(firsts).zip(seconds)
+            {
+                *parameter -= match self.method {
+                    Method::Sgd => self.learning_rate * gradient,
+                    Method::Momentum { momentum } => {
+                        *first = momentum * *first + self.learning_rate * gradient;
+                        *first
+                    }
+                    Method::Adam {
+                        beta1,
+                        beta2,
+                        epsilon,
+                    } => {
+                        *first = beta1 * *first + (1.0 - beta1) * gradient;
+                        *second = beta2 * *second + (1.0 - beta2) * gradient * gradient;
+                        let first = *first / (1.0 - beta1.powi(self.step));
+                        let second = *second / (1.0 - beta2.powi(self.step));
+                        self.learning_rate * first / (second.sqrt() + epsilon)
+                    }
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::activation::ActivationFunction;
+    use super::super::loss::Loss;
+    use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_sgd_step() {
+        let mut nn = NeuralNetwork::new(
+            &[2, 1],
+            &[ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(0),
+        );
+        let before = nn.clone();
+        let gradients = vec![Gradients {
+            weights: vec![1.0, -2.0],
+            biases: vec![0.5],
+        }];
+        Optimizer::sgd(0.1).step(&mut nn, &gradients);
+
+        let (layer, old) = (&nn.layers[0], &before.layers[0]);
+        assert!((layer.weights[0] - (old.weights[0] - 0.1)).abs() < 1e-12);
+        assert!((layer.weights[1] - (old.weights[1] + 0.2)).abs() < 1e-12);
+        assert!((layer.biases[0] - (old.biases[0] - 0.05)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn every_method_reduces_the_loss() {
+        let samples: Vec<(Vec<f64>, Vec<f64>)> = (0..20)
+            .map(|i| {
+                let x = i as f64 / 10.0 - 1.0;
+                (vec![x], vec![3.0 * x - 0.5])
+            })
+            .collect();
+        for optimizer in [
+            Optimizer::sgd(0.05),
+            Optimizer::momentum(0.05, 0.9),
+            Optimizer::adam(0.05),
+        ] {
+            let mut optimizer = optimizer;
+            let mut nn = NeuralNetwork::new(
+                &[1, 1],
+                &[ActivationFunction::None],
+                &mut SplitMix64::seed_from_u64(1),
+            );
+            let first = nn.fit(&samples, 4, Loss::MeanSquaredError, &mut optimizer);
+            let mut last = first;
+            for _ in 0..100 {
+                last = nn.fit(&samples, 4, Loss::MeanSquaredError, &mut optimizer);
+            }
+            assert!(
+                last < first / 100.0,
+                "{:?}: {first} -> {last}",
+                optimizer.method
+            );
+        }
+    }
+}
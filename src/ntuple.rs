@@ -0,0 +1,377 @@
+use crate::evaluator::Evaluator;
+use crate::game::{Actions, Game, MAX_CELL, MAX_SIZE};
+use crate::policy::Policy;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use strum::IntoEnumIterator;
+
+/// The number of values a cell can take.
+const CELL_VALUES: usize = MAX_CELL as usize + 1;
+/// The longest supported tuple, whose table has 16^6 weights.
+pub const MAX_TUPLE_LENGTH: usize = 6;
+/// The default step size of the TD updates, shared among all the weights of a position.
+pub const LEARNING_RATE: f32 = 0.1;
+
+/// The cells of a tuple, as rows and columns.
+pub type Pattern = Vec<(usize, usize)>;
+
+/// The edge and inner rows and the 2x2 squares, which together
+/// with their symmetries cover every line and square of the board.
+pub fn default_patterns(size: usize) -> Vec<Pattern> {
+    let length = size.min(4);
+    let lines = (0..size.min(2)).map(|row| (0..length).map(|col| (row, col)).collect());
+    let squares = [(0, 0), (0, 1), (1, 1)]
+        .into_iter()
+        .filter(|&(i, j)| i + 1 < size && j + 1 < size)
+        .map(|(i, j)| vec![(i, j), (i, j + 1), (i + 1, j), (i + 1, j + 1)]);
+    lines.chain(squares).collect()
+}
+
+/// Parses patterns written as row-major cell indices, with the cells of a tuple
+/// separated by commas and the tuples by semicolons, e.g. `0,1,2,3;0,1,4,5`.
+pub fn parse_patterns(text: &str, size: usize) -> Result<Vec<Pattern>, String> {
+    text.split(';')
+        .map(|tuple| {
+            let pattern = tuple
+                .split(',')
+                .map(|cell| match cell.trim().parse::<usize>() {
+                    Ok(cell) if cell < size * size => Ok((cell / size, cell % size)),
+                    _ => Err(format!("invalid cell {cell:?} for a {size}x{size} board")),
+                })
+                .collect::<Result<Pattern, _>>()?;
+            if pattern.len() > MAX_TUPLE_LENGTH {
+                return Err(format!(
+                    "tuples have at most {MAX_TUPLE_LENGTH} cells, got {}",
+                    pattern.len()
+                ));
+            }
+            Ok(pattern)
+        })
+        .collect()
+}
+
+/// An n-tuple network, which values a board as the sum of the weights
+/// looked up by the tiles in a few tuples of cells.
+///
+/// Every pattern shares its weights among the 8 symmetries of the board,
+/// so a position and its rotations and reflections have the same value.
+/// The weights are learned with TD(0) on afterstates, and estimate the
+/// score that is still to come.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NTupleNetwork {
+    size: usize,
+    patterns: Vec<Pattern>,
+    /// One table per pattern, indexed by the exponents of its cells.
+    /// `f32` keeps the tables of longer tuples small.
+    weights: Vec<Vec<f32>>,
+    /// The row-major cells of every symmetry of every pattern.
+    #[serde(skip)]
+    tuples: Vec<Vec<Vec<usize>>>,
+}
+
+impl NTupleNetwork {
+    /// Creates a network for boards of `size` with all weights 0.
+    pub fn new(size: usize, patterns: Vec<Pattern>) -> Self {
+        for pattern in &patterns {
+            assert!(!pattern.is_empty() && pattern.len() <= MAX_TUPLE_LENGTH);
+            assert!(pattern.iter().all(|&(i, j)| i < size && j < size));
+        }
+        let weights = patterns
+            .iter()
+            .map(|pattern| vec![0.0; CELL_VALUES.pow(pattern.len() as u32)])
+            .collect();
+        let mut network = Self {
+            size,
+            patterns,
+            weights,
+            tuples: vec![],
+        };
+        network.build_tuples();
+        network
+    }
+
+    fn build_tuples(&mut self) {
+        let last = self.size - 1;
+        self.tuples = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                let mut symmetries = vec![];
+                for transpose in [false, true] {
+                    for flip_rows in [false, true] {
+                        for flip_cols in [false, true] {
+                            let cells = pattern.iter().map(|&(i, j)| {
+                                let (i, j) = if transpose { (j, i) } else { (i, j) };
+                                let i = if flip_rows { last - i } else { i };
+                                let j = if flip_cols { last - j } else { j };
+                                i * self.size + j
+                            });
+                            symmetries.push(cells.collect());
+                        }
+                    }
+                }
+                symmetries
+            })
+            .collect();
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn patterns(&self) -> &[Pattern] {
+        &self.patterns
+    }
+
+    /// The indices into the weight tables of every pattern and symmetry.
+    fn features(&self, game: &Game) -> impl Iterator<Item = (usize, usize)> + '_ {
+        let mut cells = [0; MAX_SIZE * MAX_SIZE];
+        for (k, cell) in game.cells().enumerate() {
+            // Tiles beyond 2^15 share the last entry of the tables.
+            cells[k] = (cell as usize).min(CELL_VALUES - 1);
+        }
+        self.tuples
+            .iter()
+            .enumerate()
+            .flat_map(|(pattern, symmetries)| symmetries.iter().map(move |tuple| (pattern, tuple)))
+            .map(move |(pattern, tuple)| {
+                let index = tuple
+                    .iter()
+                    .fold(0, |index, &cell| index * CELL_VALUES + cells[cell]);
+                (pattern, index)
+            })
+    }
+
+    /// The estimated score that is still to come from `game`.
+    pub fn value(&self, game: &Game) -> f32 {
+        self.features(game)
+            .map(|(pattern, index)| self.weights[pattern][index])
+            .sum()
+    }
+
+    /// Moves the value of `game` by `delta`, spread evenly over its weights.
+    fn update(&mut self, game: &Game, delta: f32) {
+        let features = self.features(game).collect::<Vec<_>>();
+        let step = delta / features.len() as f32;
+        for (pattern, index) in features {
+            self.weights[pattern][index] += step;
+        }
+    }
+
+    /// Returns the move with the highest reward plus value of its afterstate,
+    /// together with the afterstate.
+    pub fn best_afterstate(&self, game: &Game) -> Option<(Actions, Game)> {
+        Actions::iter()
+            .filter_map(|action| Some((action, game.slide(action)?)))
+            .map(|(action, afterstate)| {
+                let reward = (afterstate.score - game.score) as f32;
+                (action, afterstate, reward + self.value(&afterstate))
+            })
+            .max_by(|(_, _, x), (_, _, y)| x.total_cmp(y))
+            .map(|(action, afterstate, _)| (action, afterstate))
+    }
+
+    /// Plays a game with the greedy policy and learns from it with TD(0):
+    /// the value of every afterstate moves towards the reward of the next move
+    /// plus the value of the next afterstate, or towards 0 at the end of the game.
+    /// Returns the finished game.
+    pub fn train_episode<R: Rng + ?Sized>(&mut self, learning_rate: f32, rng: &mut R) -> Game {
+        let mut game = Game::with_size(self.size, rng.gen());
+        let mut previous: Option<Game> = None;
+        loop {
+            let best = self.best_afterstate(&game);
+            let target = match best {
+                Some((_, afterstate)) => {
+                    (afterstate.score - game.score) as f32 + self.value(&afterstate)
+                }
+                None => 0.0,
+            };
+            if let Some(previous) = previous {
+                let error = target - self.value(&previous);
+                self.update(&previous, learning_rate * error);
+            }
+            let Some((_, afterstate)) = best else {
+                return game;
+            };
+            previous = Some(afterstate);
+            game = afterstate;
+            game.add_tile_with(rng);
+        }
+    }
+
+    /// Saves the network to a file in JSON format.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let file = File::create(filename)?;
+        serde_json::to_writer(file, &self)?;
+        Ok(())
+    }
+
+    /// Loads a network from a file in JSON format,
+    /// checking that its patterns and tables fit together.
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let file = File::open(filename)?;
+        let mut network: Self = serde_json::from_reader(file)?;
+        network
+            .validate()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        network.build_tuples();
+        Ok(network)
+    }
+
+    /// Checks what `new` asserts, and that there is a table of the right length per pattern.
+    fn validate(&self) -> Result<(), String> {
+        let size = self.size;
+        if !(2..=MAX_SIZE).contains(&size) {
+            return Err(format!("unsupported board size {size}"));
+        }
+        if self.weights.len() != self.patterns.len() {
+            return Err(format!(
+                "{} weight tables for {} patterns",
+                self.weights.len(),
+                self.patterns.len()
+            ));
+        }
+        for (pattern, table) in self.patterns.iter().zip(&self.weights) {
+            if pattern.is_empty() || pattern.len() > MAX_TUPLE_LENGTH {
+                return Err(format!(
+                    "tuples have 1 to {MAX_TUPLE_LENGTH} cells, got {}",
+                    pattern.len()
+                ));
+            }
+            if let Some(&(i, j)) = pattern.iter().find(|&&(i, j)| i >= size || j >= size) {
+                return Err(format!("invalid cell ({i}, {j}) for a {size}x{size} board"));
+            }
+            let expected = CELL_VALUES.pow(pattern.len() as u32);
+            if table.len() != expected {
+                return Err(format!(
+                    "expected {expected} weights for a tuple of {} cells, got {}",
+                    pattern.len(),
+                    table.len()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Plays the move with the highest reward plus value of its afterstate.
+impl Policy for NTupleNetwork {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        self.best_afterstate(game).map(|(action, _)| action)
+    }
+}
+
+/// The score so far plus the value of the position,
+/// so positions reached by different moves compare by their total score.
+impl Evaluator for NTupleNetwork {
+    fn evaluate(&self, game: &Game) -> f64 {
+        game.score as f64 + self.value(game) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::SplitMix64;
+    use rand::SeedableRng;
+    use std::fs;
+
+    #[test]
+    fn default_patterns_fit_the_board() {
+        for size in 2..=MAX_SIZE {
+            let network = NTupleNetwork::new(size, default_patterns(size));
+            assert!(!network.patterns().is_empty());
+        }
+        assert_eq!(default_patterns(4).len(), 5);
+    }
+
+    #[test]
+    fn patterns_from_text() {
+        assert_eq!(
+            parse_patterns("0,1,2;3, 4", 3),
+            Ok(vec![vec![(0, 0), (0, 1), (0, 2)], vec![(1, 0), (1, 1)]])
+        );
+        assert!(parse_patterns("0,9", 3).is_err());
+        assert!(parse_patterns("0,1,2,3,4,5,6", 4).is_err());
+        assert!(parse_patterns("0,,1", 4).is_err());
+    }
+
+    #[test]
+    fn symmetric_positions_have_the_same_value() {
+        let mut rng = SplitMix64::seed_from_u64(0);
+        let mut network = NTupleNetwork::new(4, default_patterns(4));
+        for _ in 0..20 {
+            network.train_episode(LEARNING_RATE, &mut rng);
+        }
+
+        let game = network.train_episode(0.0, &mut rng);
+        let mut transposed = game;
+        let mut mirrored = game;
+        for i in 0..4 {
+            for j in 0..4 {
+                transposed.set(j, i, game.get(i, j));
+                mirrored.set(i, 3 - j, game.get(i, j));
+            }
+        }
+        let value = network.value(&game);
+        assert_ne!(value, 0.0);
+        // The features are the same, only summed in another order.
+        assert!((value - network.value(&transposed)).abs() < 1e-3);
+        assert!((value - network.value(&mirrored)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn learns_to_play() {
+        let mut rng = SplitMix64::seed_from_u64(1);
+        let mut network = NTupleNetwork::new(3, default_patterns(3));
+        let mut scores = |network: &mut NTupleNetwork, learning_rate| {
+            (0..200)
+                .map(|_| network.train_episode(learning_rate, &mut rng).score)
+                .sum::<u32>()
+        };
+        let untrained = scores(&mut network.clone(), 0.0);
+        scores(&mut network, LEARNING_RATE);
+        let trained = scores(&mut network, 0.0);
+        assert!(trained > untrained, "{trained} <= {untrained}");
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut network = NTupleNetwork::new(3, default_patterns(3));
+        network.train_episode(LEARNING_RATE, &mut SplitMix64::seed_from_u64(2));
+        let file = "test_ntuple_network.json";
+        network.save(file).unwrap();
+        let loaded = NTupleNetwork::load(file).unwrap();
+        fs::remove_file(file).unwrap();
+
+        let game = Game::with_size(3, 5);
+        assert_eq!(loaded.weights, network.weights);
+        assert_eq!(loaded.value(&game), network.value(&game));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let valid = NTupleNetwork::new(3, vec![vec![(0, 0), (0, 1)]]);
+        let mut outside = valid.clone();
+        outside.patterns[0][1] = (0, 3);
+        let mut short = valid.clone();
+        short.weights[0].pop();
+        let mut missing = valid.clone();
+        missing.weights.clear();
+        let mut small = valid.clone();
+        small.size = 0;
+
+        let file = "test_ntuple_invalid.json";
+        for network in [outside, short, missing, small] {
+            network.save(file).unwrap();
+            let err = NTupleNetwork::load(file)
+                .err()
+                .expect("The file should be rejected");
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+        fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+use crate::evaluator::{Evaluator, Score};
+use crate::expectimax::{self, Expectimax};
+use crate::game::{Actions, Game};
+use crate::mcts::{self, Mcts};
+use crate::nn::NeuralNetwork;
+use crate::rng::SplitMix64;
+use crate::search::SearchConfig;
+
+use itertools::Itertools;
+use rand::seq::IteratorRandom;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+
+/// A strategy that chooses the moves of a game.
+pub trait Policy {
+    /// Returns the move to play in `game`, or `None` if the policy has no move,
+    /// which is always the case once the game is over.
+    fn choose(&mut self, game: &Game) -> Option<Actions>;
+}
+
+impl<P: Policy + ?Sized> Policy for &mut P {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        (**self).choose(game)
+    }
+}
+
+impl<P: Policy + ?Sized> Policy for Box<P> {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        (**self).choose(game)
+    }
+}
+
+/// Plays `game` with `policy` until it is over or the policy has no move,
+/// for at most `max_steps` moves, and returns the number of moves played.
+pub fn play<P: Policy + ?Sized>(policy: &mut P, game: &mut Game, max_steps: usize) -> usize {
+    let mut steps = 0;
+    while steps < max_steps {
+        let Some(action) = policy.choose(game) else {
+            break;
+        };
+        if !game.step(action).changed {
+            break;
+        }
+        steps += 1;
+    }
+    steps
+}
+
+/// Plays a valid move chosen uniformly at random.
+#[derive(Clone, Debug)]
+pub struct Random {
+    rng: SplitMix64,
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: SplitMix64::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Policy for Random {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        game.valid_moves().into_iter().choose(&mut self.rng)
+    }
+}
+
+/// Plays the move chosen by the flat Monte Carlo search [`mcts::simulation`].
+#[derive(Clone, Debug)]
+pub struct FlatMonteCarlo<E = Score> {
+    pub config: SearchConfig,
+    pub evaluator: E,
+    rng: SplitMix64,
+}
+
+impl<E: Evaluator> FlatMonteCarlo<E> {
+    pub fn new(config: SearchConfig, evaluator: E, seed: u64) -> Self {
+        Self {
+            config,
+            evaluator,
+            rng: SplitMix64::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<E: Evaluator> Policy for FlatMonteCarlo<E> {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        mcts::simulation(game, &self.config, &self.evaluator, &mut self.rng)
+    }
+}
+
+/// Plays the move chosen by a tree search, which keeps its tree between moves.
+#[derive(Clone, Debug)]
+pub struct TreeSearch<E = Score> {
+    pub mcts: Mcts<E>,
+    rng: SplitMix64,
+}
+
+impl<E: Evaluator> TreeSearch<E> {
+    pub fn new(mcts: Mcts<E>, seed: u64) -> Self {
+        Self {
+            mcts,
+            rng: SplitMix64::seed_from_u64(seed),
+        }
+    }
+}
+
+impl<E: Evaluator> Policy for TreeSearch<E> {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        self.mcts.search(game, &mut self.rng)
+    }
+}
+
+impl<E: Evaluator> Policy for Expectimax<E> {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        self.best_move(game)
+    }
+}
+
+/// Plays the valid move with the highest output of the network.
+impl Policy for NeuralNetwork {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        self.best_move(game)
+    }
+}
+
+/// Plays the move with the highest output of the network, valid or not.
+///
+/// An invalid move leaves the board unchanged, which ends the game, so the
+/// networks evolved with this policy are the ones that learn to avoid them.
+#[derive(Clone, Copy)]
+pub struct Unmasked<'a>(pub &'a NeuralNetwork);
+
+impl Policy for Unmasked<'_> {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        if game.is_game_over() {
+            return None;
+        }
+        let output = self.0.predict(game);
+        let arg_max = output.iter().position_max_by(|x, y| x.total_cmp(y))?;
+        Actions::try_from(arg_max).ok()
+    }
+}
+
+/// Plays a move chosen outside of the program, like the arrow key a player pressed.
+#[derive(Clone, Copy, Debug)]
+pub struct Manual(pub Actions);
+
+impl Policy for Manual {
+    fn choose(&mut self, game: &Game) -> Option<Actions> {
+        (!game.is_game_over()).then_some(self.0)
+    }
+}
+
+/// The policies that can be built without a model.
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Strategy {
+    /// Monte Carlo tree search
+    Mcts,
+    /// Flat Monte Carlo rollouts, without a tree
+    FlatMc,
+    /// Depth-limited expectimax search
+    Expectimax,
+    /// Random valid moves
+    Random,
+}
+
+impl Strategy {
+    /// Builds the policy, scoring positions with `evaluator` or the default
+    /// evaluator of the search. The Monte Carlo searches use `config`,
+    /// and `seed` seeds their randomness.
+    pub fn build(
+        self,
+        config: SearchConfig,
+        evaluator: Option<Box<dyn Evaluator>>,
+        seed: u64,
+    ) -> Box<dyn Policy> {
+        let evaluator = evaluator.unwrap_or_else(|| self.default_evaluator());
+        match self {
+            Strategy::Mcts => Box::new(TreeSearch::new(
+                Mcts::with_evaluator(config, evaluator),
+                seed,
+            )),
+            Strategy::FlatMc => Box::new(FlatMonteCarlo::new(config, evaluator, seed)),
+            Strategy::Expectimax => Box::new(Expectimax::new(evaluator)),
+            Strategy::Random => Box::new(Random::new(seed)),
+        }
+    }
+
+    fn default_evaluator(self) -> Box<dyn Evaluator> {
+        match self {
+            Strategy::Expectimax => Box::new(expectimax::default_evaluator as fn(&Game) -> f64),
+            _ => Box::new(Score),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::ActivationFunction;
+
+    #[test]
+    fn strategies_finish_games() {
+        let config = SearchConfig {
+            rollouts: 20,
+            ..Default::default()
+        };
+        for strategy in [
+            Strategy::Mcts,
+            Strategy::FlatMc,
+            Strategy::Expectimax,
+            Strategy::Random,
+        ] {
+            let mut policy = strategy.build(config, None, 0);
+            let mut game = Game::with_size(3, 1);
+            let steps = play(&mut policy, &mut game, usize::MAX);
+            assert!(game.is_game_over(), "{strategy:?}");
+            assert_eq!(steps, game.moves);
+            assert_eq!(policy.choose(&game), None);
+        }
+    }
+
+    #[test]
+    fn network_plays_valid_moves() {
+        let mut nn = NeuralNetwork::new(
+            &[9, 8, 4],
+            &[ActivationFunction::ReLU, ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(0),
+        );
+        let mut game = Game::with_size(3, 2);
+        while let Some(action) = nn.choose(&game) {
+            assert!(game.valid_moves().contains(&action));
+            game.step(action);
+        }
+        assert!(game.is_game_over());
+    }
+
+    #[test]
+    fn unmasked_network_can_choose_invalid_moves() {
+        // The outputs are the biases, which prefer Left.
+        let nn = NeuralNetwork {
+            layers: vec![crate::nn::Layer::from_weights(
+                4,
+                vec![0.0; 16],
+                vec![1.0, 0.0, 0.0, 0.0],
+                ActivationFunction::None,
+            )],
+            encoding: Default::default(),
+        };
+        let mut game = Game::with_size(2, 0);
+        game.board = crate::game::Board::empty(2);
+        game.set(0, 0, 1);
+        assert_eq!(Unmasked(&nn).choose(&game), Some(Actions::Left));
+        assert!(!game.valid_moves().contains(&Actions::Left));
+        assert_eq!(play(&mut Unmasked(&nn), &mut game, usize::MAX), 0);
+
+        let mut nn = nn;
+        assert_ne!(nn.choose(&game), Some(Actions::Left));
+    }
+
+    #[test]
+    fn random_is_reproducible() {
+        let game = Game::with_seed(0);
+        let moves = |seed| {
+            let mut policy = Random::new(seed);
+            (0..10).map(|_| policy.choose(&game)).collect::<Vec<_>>()
+        };
+        assert_eq!(moves(3), moves(3));
+    }
+}
@@ -1,9 +1,12 @@
-use crate::game::Actions;
 use crate::game::Game;
 use crate::nn::NeuralNetwork;
+use crate::policy::{Policy, Unmasked};
+use crate::replay::Replay;
 use itertools::Itertools;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Agent {
     pub nn: NeuralNetwork,
     pub scores: Vec<u32>,
@@ -23,28 +26,37 @@ impl Agent {
         }
     }
 
-    pub fn predict(&self) -> Actions {
-        let output = self.nn.forward(self.game.flatten());
-        assert!(output.len() == 4);
-        let arg_max = output
-            .iter()
-            .position_max_by(|x, y| x.total_cmp(y))
-            .expect("Cannot find maximal value");
-        Actions::try_from(arg_max).expect("Cannot map integer to action")
+    pub fn step(&mut self) -> bool {
+        self.step_with(None)
     }
 
-    pub fn step(&mut self) -> bool {
-        if self.game.is_game_over() {
+    /// Performs a step, recording it in `replay` if given.
+    fn step_with(&mut self, replay: Option<&mut Replay>) -> bool {
+        let Some(action) = Unmasked(&self.nn).choose(&self.game) else {
             return false;
-        }
-        let changed = self.game.step(self.predict());
+        };
+        let outcome = match replay {
+            Some(replay) => replay.step(&mut self.game, action),
+            None => self.game.step(action),
+        };
         self.steps += 1;
-        changed
+        outcome.changed
     }
 
     pub fn play(&mut self, max_steps: usize) {
+        self.play_with(max_steps, None)
+    }
+
+    /// Plays the current game like `play`, and returns its replay.
+    pub fn play_recorded(&mut self, max_steps: usize) -> Replay {
+        let mut replay = Replay::new(&self.game);
+        self.play_with(max_steps, Some(&mut replay));
+        replay
+    }
+
+    fn play_with(&mut self, max_steps: usize, mut replay: Option<&mut Replay>) {
         while !self.game.is_game_over() && self.steps < max_steps {
-            let changed = self.step();
+            let changed = self.step_with(replay.as_deref_mut());
             if !changed {
                 break;
             }
@@ -57,9 +69,10 @@ impl Agent {
         );
     }
 
-    pub fn reset(&mut self) {
+    /// Starts a new game of the same size, whose tiles are determined by `seed`.
+    pub fn reset(&mut self, seed: u64) {
         self.steps = 0;
-        self.game = Game::new();
+        self.game = Game::with_size(self.game.size(), seed);
     }
 
     pub fn get_highest_tile(&self) -> Option<&u32> {
@@ -75,8 +88,8 @@ impl Agent {
         self.scores.iter().sum::<u32>() as f64 / self.scores.len() as f64
     }
 
-    pub fn mutate(&mut self, rate: f64, variation: f64) {
-        self.nn.update(rate, variation)
+    pub fn mutate<R: Rng + ?Sized>(&mut self, rate: f64, variation: f64, rng: &mut R) {
+        self.nn.update(rate, variation, rng)
     }
 }
 
@@ -90,18 +103,38 @@ mod tests {
     #[test]
     fn test_2048_nn() {
         let game = Game::new();
-        let nn = NeuralNetwork::new(&[16, 16, 8, 4], &[ActivationFunction::None; 3]);
+        let nn = NeuralNetwork::new(
+            &[16, 16, 8, 4],
+            &[ActivationFunction::None; 3],
+            &mut rand::thread_rng(),
+        );
         let input = game.flatten();
-        let output = nn.forward(input);
+        let output = nn.forward(&input);
         assert!(output.len() == 4);
     }
 
     #[test]
     fn test_agent() {
-        let nn = NeuralNetwork::new(&[16, 16, 8, 4], &[ActivationFunction::None; 3]);
+        let nn = NeuralNetwork::new(
+            &[16, 16, 8, 4],
+            &[ActivationFunction::None; 3],
+            &mut rand::thread_rng(),
+        );
         let game = Game::new();
         let mut a = Agent::new(nn, game);
         a.step();
         assert!(a.steps == 1)
     }
+
+    #[test]
+    fn test_recorded_game() {
+        let nn = NeuralNetwork::new(
+            &[16, 16, 8, 4],
+            &[ActivationFunction::None; 3],
+            &mut rand::thread_rng(),
+        );
+        let mut a = Agent::new(nn, Game::with_seed(4));
+        let replay = a.play_recorded(100);
+        assert_eq!(replay.validate(), Ok(a.game));
+    }
 }
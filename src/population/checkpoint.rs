@@ -0,0 +1,103 @@
+use super::config::TrainConfig;
+use super::Population;
+
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+
+/// Everything needed to resume a training run: the configuration,
+/// and the agents with their scores, the evolution step and the random
+/// number generator of the population.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub config: TrainConfig,
+    pub population: Population,
+}
+
+impl Checkpoint {
+    /// Saves the checkpoint to a file in JSON format.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        serde_json::to_writer(&mut writer, &self)?;
+        writer.flush()
+    }
+
+    /// Loads a checkpoint from a file in JSON format.
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let reader = BufReader::new(File::open(filename)?);
+        let checkpoint = serde_json::from_reader(reader)?;
+        Ok(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nn::activation::ActivationFunction;
+    use crate::nn::encoding::InputEncoding;
+
+    fn config() -> TrainConfig {
+        TrainConfig {
+            agents: 8,
+            rounds: 2,
+            max_steps: 50,
+            evolution_steps: 3,
+            size: 3,
+            layer_sizes: vec![9, 6, 4],
+            activation_functions: vec![ActivationFunction::ReLU, ActivationFunction::None],
+            keep_proportion: 0.25,
+            crossover_probability: 0.5,
+            seed: Some(5),
+            ..Default::default()
+        }
+    }
+
+    fn generation(population: &mut Population) {
+        let config = config();
+        population.play_generation(config.rounds, config.max_steps);
+        population.evolve(
+            config.selection().as_ref(),
+            config.crossover,
+            config.crossover_probability,
+            config.mutation_rate,
+            config.mutation_variation,
+        );
+    }
+
+    fn to_json(population: &Population) -> String {
+        let checkpoint = Checkpoint {
+            config: config(),
+            population: population.clone(),
+        };
+        serde_json::to_string(&checkpoint).unwrap()
+    }
+
+    #[test]
+    fn resumes_exactly() {
+        let mut population = Population::new(
+            8,
+            3,
+            &[9, 6, 4],
+            &[ActivationFunction::ReLU, ActivationFunction::None],
+            InputEncoding::Raw,
+            5,
+        );
+        generation(&mut population);
+
+        let filename = "test_checkpoint.json";
+        Checkpoint {
+            config: config(),
+            population: population.clone(),
+        }
+        .save(filename)
+        .unwrap();
+        let mut resumed = Checkpoint::load(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(resumed.config, config());
+        assert_eq!(resumed.population.evolution_step, 1);
+
+        generation(&mut population);
+        generation(&mut resumed.population);
+        assert_eq!(to_json(&resumed.population), to_json(&population));
+    }
+}
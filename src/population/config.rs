@@ -0,0 +1,516 @@
+use crate::game::{Actions, DEFAULT_SIZE, MAX_SIZE};
+use crate::nn::activation::ActivationFunction;
+use crate::nn::crossover::Crossover;
+use crate::nn::encoding::InputEncoding;
+use crate::population::selection::{Selection, SelectionMethod};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use strum::EnumCount;
+
+/// The share of the best agents that survive an evolution step by default.
+pub const KEEP_PROPORTION: f64 = 0.02;
+/// The probability of each weight of an offspring being mutated by default.
+pub const MUTATION_RATE: f64 = 0.1;
+/// The largest change of a mutated weight by default.
+pub const MUTATION_VARIATION: f64 = 0.1;
+
+/// The settings of an evolutionary training run.
+///
+/// Settings missing from a configuration file take their default values.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrainConfig {
+    /// The number of agents in the population.
+    pub agents: usize,
+    /// The number of games every agent plays per evolution step.
+    pub rounds: usize,
+    /// The number of moves after which a game is stopped.
+    pub max_steps: usize,
+    /// The evolution step at which the run ends.
+    pub evolution_steps: usize,
+    /// The number of rows and columns of the board.
+    pub size: usize,
+    /// How boards are turned into the inputs of the networks.
+    pub encoding: InputEncoding,
+    /// The number of inputs followed by the number of outputs of every layer.
+    pub layer_sizes: Vec<usize>,
+    /// The activation function of every layer.
+    pub activation_functions: Vec<ActivationFunction>,
+    /// How the agents of the next generation are chosen.
+    pub selection: SelectionMethod,
+    /// The share of the best agents that survive an evolution step,
+    /// or that have offspring for the (μ,λ) selection.
+    pub keep_proportion: f64,
+    /// The number of agents that compete for every offspring in the
+    /// tournament selection.
+    pub tournament_size: usize,
+    /// How the two parents of an offspring are combined.
+    pub crossover: Crossover,
+    /// The probability of an offspring having two parents instead of one.
+    pub crossover_probability: f64,
+    /// The probability of each weight of an offspring being mutated.
+    pub mutation_rate: f64,
+    /// The largest change of a mutated weight.
+    pub mutation_variation: f64,
+    /// The seed of the run, random if not given. At most [`MAX_SEED`],
+    /// so that it can be saved in a TOML file.
+    pub seed: Option<u64>,
+}
+
+/// The largest seed of a configuration, the largest integer of TOML.
+pub const MAX_SEED: u64 = i64::MAX as u64;
+
+/// The layer sizes of a configuration file, if it gives them.
+#[derive(Deserialize)]
+struct GivenLayerSizes {
+    layer_sizes: Option<Vec<usize>>,
+}
+
+impl Default for TrainConfig {
+    fn default() -> Self {
+        Self {
+            agents: 1000,
+            rounds: 100,
+            max_steps: 10000,
+            evolution_steps: 10000,
+            size: DEFAULT_SIZE,
+            encoding: InputEncoding::Raw,
+            layer_sizes: vec![DEFAULT_SIZE * DEFAULT_SIZE, 128, 64, Actions::COUNT],
+            activation_functions: vec![
+                ActivationFunction::ReLU,
+                ActivationFunction::ReLU,
+                ActivationFunction::None,
+            ],
+            selection: SelectionMethod::Truncation,
+            keep_proportion: KEEP_PROPORTION,
+            tournament_size: 3,
+            crossover: Crossover::Uniform,
+            crossover_probability: 0.0,
+            mutation_rate: MUTATION_RATE,
+            mutation_variation: MUTATION_VARIATION,
+            seed: None,
+        }
+    }
+}
+
+/// The reasons why a training configuration cannot be used.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(String),
+    /// The first layer does not take the inputs of the encoding.
+    InputSize {
+        expected: usize,
+        found: usize,
+    },
+    /// The last layer does not have an output per action.
+    OutputSize {
+        expected: usize,
+        found: usize,
+    },
+    /// There is not an activation function per layer.
+    ActivationFunctions {
+        expected: usize,
+        found: usize,
+    },
+    Invalid {
+        field: &'static str,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "{err}"),
+            ConfigError::Parse(err) => write!(f, "invalid configuration: {err}"),
+            ConfigError::InputSize { expected, found } => write!(
+                f,
+                "the first layer has {found} inputs, the encoding has {expected}"
+            ),
+            ConfigError::OutputSize { expected, found } => write!(
+                f,
+                "the last layer has {found} outputs, expected one per action ({expected})"
+            ),
+            ConfigError::ActivationFunctions { expected, found } => {
+                write!(f, "{found} activation functions for {expected} layers")
+            }
+            ConfigError::Invalid { field, reason } => write!(f, "{field} {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+/// Parses `text` as TOML if `toml` is set and as JSON otherwise.
+fn parse<T: DeserializeOwned>(text: &str, toml: bool) -> Result<T, ConfigError> {
+    if toml {
+        toml::from_str(text).map_err(|err| ConfigError::Parse(err.to_string()))
+    } else {
+        serde_json::from_str(text).map_err(|err| ConfigError::Parse(err.to_string()))
+    }
+}
+
+/// Whether `path` names a TOML file, otherwise it is JSON.
+fn is_toml(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "toml")
+}
+
+impl TrainConfig {
+    /// Checks that the settings can be trained with.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |field, reason| Err(ConfigError::Invalid { field, reason });
+        if !(2..=MAX_SIZE).contains(&self.size) {
+            return invalid("size", "must be between 2 and 8");
+        }
+        if self.agents == 0 {
+            return invalid("agents", "must be positive");
+        }
+        if self.rounds == 0 {
+            return invalid("rounds", "must be positive");
+        }
+        if !(self.keep_proportion > 0.0 && self.keep_proportion <= 1.0) {
+            return invalid("keep_proportion", "must be in (0, 1]");
+        }
+        if self.tournament_size == 0 {
+            return invalid("tournament_size", "must be positive");
+        }
+        if !(0.0..=1.0).contains(&self.crossover_probability) {
+            return invalid("crossover_probability", "must be in [0, 1]");
+        }
+        if !(0.0..=1.0).contains(&self.mutation_rate) {
+            return invalid("mutation_rate", "must be in [0, 1]");
+        }
+        if self.mutation_variation.is_nan() || self.mutation_variation <= 0.0 {
+            return invalid("mutation_variation", "must be positive");
+        }
+        if self.seed.is_some_and(|seed| seed > MAX_SEED) {
+            return invalid("seed", "must be at most 2^63 - 1 to fit into TOML");
+        }
+        if self.layer_sizes.len() < 2 || self.layer_sizes.contains(&0) {
+            return invalid("layer_sizes", "must have at least two sizes, all positive");
+        }
+
+        let expected = self.encoding.input_size(self.size);
+        if self.layer_sizes[0] != expected {
+            return Err(ConfigError::InputSize {
+                expected,
+                found: self.layer_sizes[0],
+            });
+        }
+        let outputs = *self.layer_sizes.last().unwrap();
+        if outputs != Actions::COUNT {
+            return Err(ConfigError::OutputSize {
+                expected: Actions::COUNT,
+                found: outputs,
+            });
+        }
+        let layers = self.layer_sizes.len() - 1;
+        if self.activation_functions.len() != layers {
+            return Err(ConfigError::ActivationFunctions {
+                expected: layers,
+                found: self.activation_functions.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Builds the selection of the configuration.
+    pub fn selection(&self) -> Box<dyn Selection> {
+        self.selection
+            .build(self.keep_proportion, self.tournament_size)
+    }
+
+    /// Loads a configuration, from a TOML file if the name ends with `.toml`
+    /// and from a JSON file otherwise.
+    /// It is not validated, as options applied afterwards may still change it.
+    ///
+    /// Like the command line flags, a file that does not give the layer sizes
+    /// resizes the first layer to the inputs of its board size and encoding.
+    pub fn load(filename: &str) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(filename)?;
+        let toml = is_toml(Path::new(filename));
+        let mut config: Self = parse(&text, toml)?;
+        let given: GivenLayerSizes = parse(&text, toml)?;
+        if given.layer_sizes.is_none() {
+            config.fit_inputs();
+        }
+        Ok(config)
+    }
+
+    /// Resizes the first layer to the inputs of the board size and the encoding.
+    fn fit_inputs(&mut self) {
+        if let Some(inputs) = self.layer_sizes.first_mut() {
+            *inputs = self.encoding.input_size(self.size);
+        }
+    }
+
+    /// Saves the configuration, to a TOML file if the name ends with `.toml`
+    /// and to a JSON file otherwise.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let text = if is_toml(Path::new(filename)) {
+            toml::to_string(self).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        fs::write(filename, text)
+    }
+}
+
+/// Command line flags that take precedence over a training configuration.
+#[derive(clap::Args, Clone, Default, PartialEq, Debug)]
+pub struct TrainOverrides {
+    /// Number of agents in the population
+    #[arg(long)]
+    pub agents: Option<usize>,
+
+    /// Number of games every agent plays per evolution step
+    #[arg(long)]
+    pub rounds: Option<usize>,
+
+    /// Number of moves after which a game is stopped
+    #[arg(long)]
+    pub max_steps: Option<usize>,
+
+    /// Evolution step at which the training ends
+    #[arg(long)]
+    pub evolution_steps: Option<usize>,
+
+    /// Number of rows and columns of the board
+    #[arg(long)]
+    pub size: Option<usize>,
+
+    /// How boards are turned into the inputs of the networks
+    #[arg(long, value_enum)]
+    pub encoding: Option<InputEncoding>,
+
+    /// Number of inputs followed by the number of outputs of every layer, e.g. "16,128,64,4"
+    #[arg(long, value_delimiter = ',')]
+    pub layer_sizes: Option<Vec<usize>>,
+
+    /// Activation function of every layer, e.g. "relu,relu,none"
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub activation_functions: Option<Vec<ActivationFunction>>,
+
+    /// How the agents of the next generation are chosen
+    #[arg(long, value_enum)]
+    pub selection: Option<SelectionMethod>,
+
+    /// Share of the best agents that survive an evolution step,
+    /// or that have offspring for the (μ,λ) selection
+    #[arg(long)]
+    pub keep_proportion: Option<f64>,
+
+    /// Number of agents that compete for every offspring in the tournament selection
+    #[arg(long)]
+    pub tournament_size: Option<usize>,
+
+    /// How the two parents of an offspring are combined
+    #[arg(long, value_enum)]
+    pub crossover: Option<Crossover>,
+
+    /// Probability of an offspring having two parents instead of one
+    #[arg(long)]
+    pub crossover_probability: Option<f64>,
+
+    /// Probability of each weight of an offspring being mutated
+    #[arg(long)]
+    pub mutation_rate: Option<f64>,
+
+    /// Largest change of a mutated weight
+    #[arg(long)]
+    pub mutation_variation: Option<f64>,
+
+    /// Seed for a reproducible training run
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+impl TrainOverrides {
+    /// Replaces the settings of `config` that were given on the command line.
+    ///
+    /// When the board size or the encoding changes but the layer sizes are
+    /// not given, the first layer is resized to the new inputs.
+    pub fn apply(&self, config: &mut TrainConfig) {
+        fn set<T: Clone>(setting: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *setting = value.clone();
+            }
+        }
+        set(&mut config.agents, &self.agents);
+        set(&mut config.rounds, &self.rounds);
+        set(&mut config.max_steps, &self.max_steps);
+        set(&mut config.evolution_steps, &self.evolution_steps);
+        set(&mut config.size, &self.size);
+        set(&mut config.encoding, &self.encoding);
+        set(&mut config.layer_sizes, &self.layer_sizes);
+        set(&mut config.activation_functions, &self.activation_functions);
+        set(&mut config.selection, &self.selection);
+        set(&mut config.keep_proportion, &self.keep_proportion);
+        set(&mut config.tournament_size, &self.tournament_size);
+        set(&mut config.crossover, &self.crossover);
+        set(
+            &mut config.crossover_probability,
+            &self.crossover_probability,
+        );
+        set(&mut config.mutation_rate, &self.mutation_rate);
+        set(&mut config.mutation_variation, &self.mutation_variation);
+        if self.seed.is_some() {
+            config.seed = self.seed;
+        }
+
+        let inputs_changed = self.size.is_some() || self.encoding.is_some();
+        if inputs_changed && self.layer_sizes.is_none() {
+            config.fit_inputs();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        assert!(TrainConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn validation() {
+        let config = TrainConfig {
+            encoding: InputEncoding::OneHot,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::InputSize {
+                expected: 272,
+                found: 16
+            })
+        ));
+
+        let config = TrainConfig {
+            layer_sizes: vec![16, 8, 3],
+            activation_functions: vec![ActivationFunction::ReLU; 2],
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::OutputSize { found: 3, .. })
+        ));
+
+        let config = TrainConfig {
+            layer_sizes: vec![16, 4],
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::ActivationFunctions {
+                expected: 1,
+                found: 3
+            })
+        ));
+
+        let config = TrainConfig {
+            keep_proportion: 0.0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid {
+                field: "keep_proportion",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn overrides() {
+        let mut config = TrainConfig::default();
+        TrainOverrides {
+            rounds: Some(3),
+            size: Some(3),
+            encoding: Some(InputEncoding::OneHot),
+            seed: Some(1),
+            ..Default::default()
+        }
+        .apply(&mut config);
+        assert_eq!(config.rounds, 3);
+        assert_eq!(config.seed, Some(1));
+        assert_eq!(config.layer_sizes, [153, 128, 64, 4]);
+        assert!(config.validate().is_ok());
+
+        TrainOverrides {
+            size: Some(4),
+            layer_sizes: Some(vec![10, 4]),
+            ..Default::default()
+        }
+        .apply(&mut config);
+        assert_eq!(config.layer_sizes, [10, 4]);
+    }
+
+    #[test]
+    fn toml_and_json_files() {
+        let toml = "agents = 50\nsize = 3\nlayer_sizes = [9, 16, 4]\n\
+                    activation_functions = [\"Tanh\", \"None\"]\nseed = 7\n\
+                    selection = \"MuPlusLambda\"\ncrossover = \"Blend\"\n";
+        let filename = "test_train_config.toml";
+        fs::write(filename, toml).unwrap();
+        let config = TrainConfig::load(filename).unwrap();
+        fs::remove_file(filename).unwrap();
+        assert_eq!(config.agents, 50);
+        assert_eq!(config.seed, Some(7));
+        assert_eq!(config.selection, SelectionMethod::MuPlusLambda);
+        assert_eq!(config.crossover, Crossover::Blend);
+        assert_eq!(config.rounds, TrainConfig::default().rounds);
+
+        for filename in ["test_train_config.json", "test_train_config_saved.toml"] {
+            config.save(filename).unwrap();
+            let loaded = TrainConfig::load(filename).unwrap();
+            fs::remove_file(filename).unwrap();
+            assert_eq!(loaded, config);
+        }
+
+        // The first layer follows the board size, unless the file gives the layers.
+        let filename = "test_train_config_partial.toml";
+        fs::write(filename, "size = 3\n").unwrap();
+        let config = TrainConfig::load(filename).unwrap();
+        fs::remove_file(filename).unwrap();
+        assert_eq!(config.layer_sizes, [9, 128, 64, 4]);
+        assert!(config.validate().is_ok());
+
+        // Seeds beyond the integers of TOML are rejected, the largest one is saved.
+        let mut config = TrainConfig {
+            seed: Some(MAX_SEED + 1),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ConfigError::Invalid { field: "seed", .. })
+        ));
+        config.seed = Some(MAX_SEED);
+        let filename = "test_train_config_seed.toml";
+        config.save(filename).unwrap();
+        let loaded = TrainConfig::load(filename).unwrap();
+        fs::remove_file(filename).unwrap();
+        assert_eq!(loaded.seed, Some(MAX_SEED));
+
+        let filename = "test_train_config_unknown.toml";
+        fs::write(filename, "agent = 50\n").unwrap();
+        let result = TrainConfig::load(filename);
+        fs::remove_file(filename).unwrap();
+        assert!(matches!(result, Err(ConfigError::Parse(_))));
+    }
+}
@@ -1,46 +1,75 @@
 pub mod agent;
+pub mod checkpoint;
+pub mod config;
+pub mod selection;
 
 use crate::{
     game::Game,
-    nn::{activation::ActivationFunction, NeuralNetwork},
+    nn::{
+        activation::ActivationFunction, crossover::Crossover, encoding::InputEncoding,
+        NeuralNetwork,
+    },
+    rng::SplitMix64,
 };
 use agent::Agent;
 use itertools::Itertools;
-use rand::Rng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
-
+use selection::Selection;
+use serde::{Deserialize, Serialize};
+
+/// A population of agents that is evolved over time.
+///
+/// All randomness (initial weights, games and mutations) comes from a
+/// generator seeded at construction, so a seed determines the whole run.
+/// The generator is saved with the population, so a run resumed from a
+/// [`checkpoint::Checkpoint`] continues exactly like the run that saved it.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Population {
     pub agents: Vec<Agent>,
     pub evolution_step: usize,
+    rng: SplitMix64,
 }
 
 impl Population {
     pub fn new(
         n_agents: usize,
+        board_size: usize,
         layer_sizes: &[usize],
         activation_functions: &[ActivationFunction],
+        encoding: InputEncoding,
+        seed: u64,
     ) -> Self {
+        assert_eq!(layer_sizes[0], encoding.input_size(board_size));
+        let mut rng = SplitMix64::seed_from_u64(seed);
         let mut agents = vec![];
         for _ in 0..n_agents {
             agents.push(Agent::new(
-                NeuralNetwork::new(layer_sizes, activation_functions),
-                Game::new(),
+                NeuralNetwork::new(layer_sizes, activation_functions, &mut rng)
+                    .with_encoding(encoding),
+                Game::with_size(board_size, rng.gen()),
             ));
         }
         Self {
             agents,
             evolution_step: 0,
+            rng,
         }
     }
 
-    pub fn from_nn(n_agents: usize, nn: NeuralNetwork) -> Self {
+    pub fn from_nn(n_agents: usize, board_size: usize, nn: NeuralNetwork, seed: u64) -> Self {
+        let mut rng = SplitMix64::seed_from_u64(seed);
         let mut agents = vec![];
         for _ in 0..n_agents {
-            agents.push(Agent::new(nn.clone(), Game::new()));
+            agents.push(Agent::new(
+                nn.clone(),
+                Game::with_size(board_size, rng.gen()),
+            ));
         }
         Self {
             agents,
             evolution_step: 0,
+            rng,
         }
     }
 
@@ -52,10 +81,25 @@ impl Population {
         self.agents.par_iter_mut().for_each(|a| a.play(max_steps));
     }
 
-    pub fn resert_agents(&mut self) {
-        for a in self.agents.iter_mut() {
-            a.reset()
+    /// Evaluates every agent on the same `rounds` games, with common random
+    /// numbers: the games are drawn once for the generation, so the scores
+    /// differ only because of the agents. The scores of earlier generations
+    /// are forgotten.
+    ///
+    /// Returns the seeds of the games, which replay them with [`Game::with_size`].
+    pub fn play_generation(&mut self, rounds: usize, max_steps: usize) -> Vec<u64> {
+        let seeds: Vec<u64> = (0..rounds).map(|_| self.rng.gen()).collect();
+        for agent in &mut self.agents {
+            agent.scores.clear();
+            agent.highest_tiles.clear();
+        }
+        for &seed in &seeds {
+            for agent in &mut self.agents {
+                agent.reset(seed);
+            }
+            self.play(max_steps);
         }
+        seeds
     }
 
     pub fn get_scores(&self) -> Vec<f64> {
@@ -71,36 +115,42 @@ impl Population {
         self.agents.get(arg_max)
     }
 
-    fn get_best_agents(&self, proportion: f64) -> Vec<&Agent> {
-        let mut agents_and_scores: Vec<(&Agent, f64)> =
-            self.agents.iter().zip(self.get_scores()).collect();
-
+    /// Replaces the agents with the next generation: the survivors of
+    /// `selection` unchanged, then mutated offspring of the parents it chooses.
+    /// With probability `crossover_probability`, an offspring is the child of
+    /// two parents, combined by `crossover`.
+    pub fn evolve(
+        &mut self,
+        selection: &dyn Selection,
+        crossover: Crossover,
+        crossover_probability: f64,
+        prop_mutate: f64,
+        mutation_rate: f64,
+    ) {
+        let scores = self.get_scores();
         // Sort by scores in descending order
-        agents_and_scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-
-        // Calculate the number of top agents to take
-        let top_n = (agents_and_scores.len() as f64 * proportion).ceil() as usize;
-
-        // Collect the top N agents
-        agents_and_scores
-            .iter()
-            .take(top_n)
-            .map(|(agent, _)| *agent)
-            .collect()
-    }
-
-    pub fn evolve(&mut self, prop_keep: f64, prop_mutate: f64, mutation_rate: f64) {
-        let best = self.get_best_agents(prop_keep);
+        let ranking: Vec<usize> = (0..self.agents.len())
+            .sorted_by(|&a, &b| scores[b].total_cmp(&scores[a]))
+            .collect();
+        let ranked_scores: Vec<f64> = ranking.iter().map(|&i| scores[i]).collect();
         let n = self.agents.len();
 
-        let mut new_agents: Vec<Agent> = best.iter().map(|a| (**a).clone()).collect();
-
-        let diff = n - new_agents.len();
-        let mut rng = rand::thread_rng();
-        for _ in 0..diff {
-            let idx = rng.gen_range(0..best.len());
-            let mut new_agent: Agent = (*best.get(idx).expect("Could not get agent")).to_owned();
-            new_agent.mutate(prop_mutate, mutation_rate);
+        let survivors = selection.survivors(n).min(n);
+        let mut new_agents: Vec<Agent> = ranking[..survivors]
+            .iter()
+            .map(|&i| self.agents[i].clone())
+            .collect();
+
+        for offspring in 0..n - survivors {
+            let parent = selection.parent(&ranked_scores, offspring, &mut self.rng);
+            let mut new_agent = self.agents[ranking[parent]].clone();
+            // Without crossovers, no number is drawn so the runs stay the same.
+            if crossover_probability > 0.0 && self.rng.gen::<f64>() < crossover_probability {
+                let mate = selection.mate(&ranked_scores, offspring, &mut self.rng);
+                let mate = &self.agents[ranking[mate]].nn;
+                new_agent.nn = new_agent.nn.crossover(mate, crossover, &mut self.rng);
+            }
+            new_agent.mutate(prop_mutate, mutation_rate, &mut self.rng);
             new_agents.push(new_agent);
         }
 
@@ -108,3 +158,40 @@ impl Population {
         self.evolution_step += 1;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agents_play_the_same_games() {
+        let nn = NeuralNetwork::new(
+            &[9, 4],
+            &[ActivationFunction::None],
+            &mut SplitMix64::seed_from_u64(0),
+        );
+        let mut population = Population::from_nn(5, 3, nn.clone(), 1);
+        population.agents[0].scores = vec![100_000];
+
+        let seeds = population.play_generation(3, 200);
+        assert_eq!(seeds.len(), 3);
+        let scores = population.get_scores();
+        assert!(scores.iter().all(|&score| score == scores[0]));
+
+        // The seeds replay the games of the agents.
+        let mut agent = Agent::new(nn, Game::with_size(3, seeds[2]));
+        agent.play(200);
+        assert_eq!(agent.game, population.agents[0].game);
+        assert_eq!(
+            population.agents[0].scores,
+            seeds
+                .iter()
+                .map(|&seed| {
+                    agent.reset(seed);
+                    agent.play(200);
+                    agent.game.score
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+}
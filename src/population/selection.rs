@@ -0,0 +1,298 @@
+//! How the parents of the next generation are chosen.
+//!
+//! A selection only sees the scores of the agents, sorted from the best
+//! to the worst, and answers with positions in that ranking.
+
+use crate::rng::SplitMix64;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Chooses which agents survive an evolution step and which ones have
+/// offspring.
+pub trait Selection {
+    /// Returns how many of the best agents of a population of `n` are kept
+    /// unchanged in the next generation.
+    fn survivors(&self, n: usize) -> usize;
+
+    /// Returns the rank of the parent of the `offspring`-th new agent,
+    /// given the scores sorted in descending order.
+    fn parent(&self, scores: &[f64], offspring: usize, rng: &mut SplitMix64) -> usize;
+
+    /// Returns the rank of the second parent of the `offspring`-th new agent,
+    /// for offspring that come from a crossover.
+    fn mate(&self, scores: &[f64], offspring: usize, rng: &mut SplitMix64) -> usize {
+        self.parent(scores, offspring, rng)
+    }
+}
+
+/// The number of agents that a share of a population of `n` stands for,
+/// at least one.
+fn share(n: usize, proportion: f64) -> usize {
+    ((n as f64 * proportion).ceil() as usize).clamp(1, n.max(1))
+}
+
+/// Keeps the best agents, and gives offspring to randomly chosen ones of them.
+#[derive(Clone, Copy, Debug)]
+pub struct Truncation {
+    pub keep_proportion: f64,
+}
+
+impl Selection for Truncation {
+    fn survivors(&self, n: usize) -> usize {
+        share(n, self.keep_proportion)
+    }
+
+    fn parent(&self, scores: &[f64], _offspring: usize, rng: &mut SplitMix64) -> usize {
+        rng.gen_range(0..self.survivors(scores.len()))
+    }
+}
+
+/// Keeps the best agents, and gives offspring to the best of `size` agents
+/// drawn from the whole population.
+#[derive(Clone, Copy, Debug)]
+pub struct Tournament {
+    pub keep_proportion: f64,
+    pub size: usize,
+}
+
+impl Selection for Tournament {
+    fn survivors(&self, n: usize) -> usize {
+        share(n, self.keep_proportion)
+    }
+
+    fn parent(&self, scores: &[f64], _offspring: usize, rng: &mut SplitMix64) -> usize {
+        (0..self.size.max(1))
+            .map(|_| rng.gen_range(0..scores.len()))
+            .min()
+            .unwrap()
+    }
+}
+
+/// Keeps the best agents, and gives offspring to agents drawn with
+/// probabilities proportional to their scores.
+#[derive(Clone, Copy, Debug)]
+pub struct Roulette {
+    pub keep_proportion: f64,
+}
+
+impl Selection for Roulette {
+    fn survivors(&self, n: usize) -> usize {
+        share(n, self.keep_proportion)
+    }
+
+    fn parent(&self, scores: &[f64], _offspring: usize, rng: &mut SplitMix64) -> usize {
+        let total: f64 = scores.iter().map(|score| score.max(0.0)).sum();
+        if total <= 0.0 {
+            return rng.gen_range(0..scores.len());
+        }
+        let mut remaining = rng.gen::<f64>() * total;
+        for (rank, score) in scores.iter().enumerate() {
+            remaining -= score.max(0.0);
+            if remaining < 0.0 {
+                return rank;
+            }
+        }
+        scores.len() - 1
+    }
+}
+
+/// Keeps the best agents, and gives offspring to agents drawn with
+/// probabilities proportional to their rank, from 1 for the worst agent
+/// to `n` for the best one, whatever the gaps between their scores.
+#[derive(Clone, Copy, Debug)]
+pub struct Rank {
+    pub keep_proportion: f64,
+}
+
+impl Selection for Rank {
+    fn survivors(&self, n: usize) -> usize {
+        share(n, self.keep_proportion)
+    }
+
+    fn parent(&self, scores: &[f64], _offspring: usize, rng: &mut SplitMix64) -> usize {
+        let n = scores.len();
+        let mut remaining = rng.gen_range(0..n * (n + 1) / 2);
+        for rank in 0..n {
+            let weight = n - rank;
+            if remaining < weight {
+                return rank;
+            }
+            remaining -= weight;
+        }
+        n - 1
+    }
+}
+
+/// The (μ,λ) evolution strategy: the μ best agents have the same number of
+/// offspring each, and the offspring replace the whole population.
+/// The second parents of crossovers are random among the μ best agents.
+#[derive(Clone, Copy, Debug)]
+pub struct MuCommaLambda {
+    /// The share μ of the population that has offspring.
+    pub parent_proportion: f64,
+}
+
+impl Selection for MuCommaLambda {
+    fn survivors(&self, _n: usize) -> usize {
+        0
+    }
+
+    fn parent(&self, scores: &[f64], offspring: usize, _rng: &mut SplitMix64) -> usize {
+        offspring % share(scores.len(), self.parent_proportion)
+    }
+
+    fn mate(&self, scores: &[f64], _offspring: usize, rng: &mut SplitMix64) -> usize {
+        rng.gen_range(0..share(scores.len(), self.parent_proportion))
+    }
+}
+
+/// The (μ+λ) evolution strategy: the μ best agents survive and have the
+/// same number of offspring each.
+/// The second parents of crossovers are random among the μ best agents.
+#[derive(Clone, Copy, Debug)]
+pub struct MuPlusLambda {
+    /// The share μ of the population that survives and has offspring.
+    pub parent_proportion: f64,
+}
+
+impl Selection for MuPlusLambda {
+    fn survivors(&self, n: usize) -> usize {
+        share(n, self.parent_proportion)
+    }
+
+    fn parent(&self, scores: &[f64], offspring: usize, _rng: &mut SplitMix64) -> usize {
+        offspring % self.survivors(scores.len())
+    }
+
+    fn mate(&self, scores: &[f64], _offspring: usize, rng: &mut SplitMix64) -> usize {
+        rng.gen_range(0..self.survivors(scores.len()))
+    }
+}
+
+/// The selections that can be chosen in a training configuration.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelectionMethod {
+    /// Random parents among the best agents
+    #[default]
+    Truncation,
+    /// The best of a few random agents
+    Tournament,
+    /// Random parents, with probabilities proportional to their scores
+    Roulette,
+    /// Random parents, with probabilities proportional to their ranks
+    Rank,
+    /// (μ,λ): the best agents are replaced by their offspring
+    MuCommaLambda,
+    /// (μ+λ): the best agents survive next to their offspring
+    MuPlusLambda,
+}
+
+impl SelectionMethod {
+    /// Builds the selection, where `keep_proportion` is the share of the
+    /// population that survives, or that has offspring for (μ,λ).
+    pub fn build(self, keep_proportion: f64, tournament_size: usize) -> Box<dyn Selection> {
+        match self {
+            SelectionMethod::Truncation => Box::new(Truncation { keep_proportion }),
+            SelectionMethod::Tournament => Box::new(Tournament {
+                keep_proportion,
+                size: tournament_size,
+            }),
+            SelectionMethod::Roulette => Box::new(Roulette { keep_proportion }),
+            SelectionMethod::Rank => Box::new(Rank { keep_proportion }),
+            SelectionMethod::MuCommaLambda => Box::new(MuCommaLambda {
+                parent_proportion: keep_proportion,
+            }),
+            SelectionMethod::MuPlusLambda => Box::new(MuPlusLambda {
+                parent_proportion: keep_proportion,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    /// Counts how often every rank is chosen as a parent.
+    fn parents(selection: &dyn Selection, scores: &[f64], offspring: usize) -> Vec<usize> {
+        let mut rng = SplitMix64::seed_from_u64(0);
+        let mut counts = vec![0; scores.len()];
+        for i in 0..offspring {
+            counts[selection.parent(scores, i, &mut rng)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn truncation_chooses_among_the_best() {
+        let selection = Truncation {
+            keep_proportion: 0.25,
+        };
+        assert_eq!(selection.survivors(10), 3);
+        let counts = parents(
+            &selection,
+            &[8.0, 7.0, 6.0, 5.0, 4.0, 3.0, 2.0, 1.0, 0.0, 0.0],
+            300,
+        );
+        assert!(counts[..3].iter().all(|&count| count > 0));
+        assert!(counts[3..].iter().all(|&count| count == 0));
+    }
+
+    #[test]
+    fn random_selections_favour_the_best() {
+        let scores = [40.0, 30.0, 20.0, 10.0];
+        for selection in [
+            &Tournament {
+                keep_proportion: 0.25,
+                size: 2,
+            } as &dyn Selection,
+            &Roulette {
+                keep_proportion: 0.25,
+            },
+            &Rank {
+                keep_proportion: 0.25,
+            },
+        ] {
+            assert_eq!(selection.survivors(4), 1);
+            let counts = parents(selection, &scores, 10000);
+            assert!(
+                counts.windows(2).all(|pair| pair[0] > pair[1]),
+                "{counts:?}"
+            );
+            assert!(counts[3] > 0);
+        }
+
+        // Rank selection ignores the gaps between the scores.
+        let rank = Rank {
+            keep_proportion: 0.25,
+        };
+        let counts = parents(&rank, &[1000.0, 1.0, 0.5, 0.0], 10000);
+        assert!((2500..3500).contains(&counts[1]), "{counts:?}");
+        // Roulette selection without any score is uniform.
+        let counts = parents(
+            &Roulette {
+                keep_proportion: 0.25,
+            },
+            &[0.0; 4],
+            10000,
+        );
+        assert!(counts.iter().all(|&count| count > 2000), "{counts:?}");
+    }
+
+    #[test]
+    fn evolution_strategies_share_the_offspring() {
+        let scores = [5.0, 4.0, 3.0, 2.0, 1.0, 0.0];
+        let comma = MuCommaLambda {
+            parent_proportion: 0.5,
+        };
+        assert_eq!(comma.survivors(6), 0);
+        assert_eq!(parents(&comma, &scores, 6), [2, 2, 2, 0, 0, 0]);
+
+        let plus = MuPlusLambda {
+            parent_proportion: 0.5,
+        };
+        assert_eq!(plus.survivors(6), 3);
+        assert_eq!(parents(&plus, &scores, 3), [1, 1, 1, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::game::{Actions, Game, MoveOutcome, Spawn, MAX_SIZE};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io;
+
+/// A single recorded move: the action and the tile spawned afterwards.
+///
+/// It is serialised as a single `u16`: bits 0-1 hold the action,
+/// bit 2 whether a tile was spawned, bit 3 whether it was a 4,
+/// and bits 4-6 and 7-9 its row and column.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(into = "u16", try_from = "u16")]
+pub struct ReplayStep {
+    pub action: Actions,
+    pub spawn: Option<Spawn>,
+}
+
+impl From<ReplayStep> for u16 {
+    fn from(step: ReplayStep) -> Self {
+        let mut packed = step.action as u16;
+        if let Some(spawn) = step.spawn {
+            packed |= 1 << 2;
+            packed |= ((spawn.value == 2) as u16) << 3;
+            packed |= (spawn.row as u16) << 4;
+            packed |= (spawn.col as u16) << 7;
+        }
+        packed
+    }
+}
+
+impl TryFrom<u16> for ReplayStep {
+    type Error = String;
+
+    fn try_from(packed: u16) -> Result<Self, Self::Error> {
+        if packed >> 10 != 0 {
+            return Err(format!("Invalid replay step {packed}"));
+        }
+        let action = Actions::try_from((packed & 0b11) as usize)
+            .map_err(|_| format!("Invalid action in replay step {packed}"))?;
+        let spawn = (packed & (1 << 2) != 0).then_some(Spawn {
+            row: ((packed >> 4) & 0b111) as usize,
+            col: ((packed >> 7) & 0b111) as usize,
+            value: if packed & (1 << 3) != 0 { 2 } else { 1 },
+        });
+        Ok(ReplayStep { action, spawn })
+    }
+}
+
+/// A compact record of a game, which can be validated by re-simulating it.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Replay {
+    pub size: usize,
+    pub seed: u64,
+    /// The exponents of the initial board in row-major order.
+    pub initial: Vec<u32>,
+    pub steps: Vec<ReplayStep>,
+}
+
+/// The reasons why a replay does not match the game it is re-simulated with.
+#[derive(Debug, PartialEq)]
+pub enum ReplayError {
+    InvalidSize(usize),
+    InitialBoard,
+    InvalidMove(usize),
+    SpawnMismatch(usize),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::InvalidSize(size) => write!(f, "unsupported board size {size}"),
+            ReplayError::InitialBoard => write!(f, "the initial board does not match the seed"),
+            ReplayError::InvalidMove(step) => write!(f, "step {step} does not change the board"),
+            ReplayError::SpawnMismatch(step) => {
+                write!(f, "step {step} spawned a different tile")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Replay {
+    /// Starts a replay of `game`, which must not have been played yet.
+    pub fn new(game: &Game) -> Self {
+        Self {
+            size: game.size(),
+            seed: game.seed(),
+            initial: game.cells().collect(),
+            steps: vec![],
+        }
+    }
+
+    /// Records the outcome of a step that changed the board.
+    pub fn record(&mut self, outcome: &MoveOutcome) {
+        self.steps.push(ReplayStep {
+            action: outcome.action,
+            spawn: outcome.spawn,
+        });
+    }
+
+    /// Performs a step on `game` and records it, if the board changed.
+    pub fn step(&mut self, game: &mut Game, action: Actions) -> MoveOutcome {
+        let outcome = game.step(action);
+        if outcome.changed {
+            self.record(&outcome);
+        }
+        outcome
+    }
+
+    /// Re-simulates the replay using `Game::step` and returns the final game.
+    pub fn validate(&self) -> Result<Game, ReplayError> {
+        if !(2..=MAX_SIZE).contains(&self.size) {
+            return Err(ReplayError::InvalidSize(self.size));
+        }
+        let mut game = Game::with_size(self.size, self.seed);
+        if !game.cells().eq(self.initial.iter().copied()) {
+            return Err(ReplayError::InitialBoard);
+        }
+        for (index, step) in self.steps.iter().enumerate() {
+            let outcome = game.step(step.action);
+            if !outcome.changed {
+                return Err(ReplayError::InvalidMove(index));
+            }
+            if outcome.spawn != step.spawn {
+                return Err(ReplayError::SpawnMismatch(index));
+            }
+        }
+        Ok(game)
+    }
+
+    /// Saves the replay to a file in JSON format.
+    pub fn save(&self, filename: &str) -> io::Result<()> {
+        let file = File::create(filename)?;
+        serde_json::to_writer(file, &self)?;
+        Ok(())
+    }
+
+    /// Loads a replay from a file in JSON format.
+    pub fn load(filename: &str) -> io::Result<Self> {
+        let file = File::open(filename)?;
+        let replay = serde_json::from_reader(file)?;
+        Ok(replay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_game(seed: u64, moves: usize) -> (Replay, Game) {
+        let mut game = Game::with_size(3, seed);
+        let mut replay = Replay::new(&game);
+        while !game.is_game_over() && game.moves < moves {
+            let action = game.valid_moves()[game.moves % game.valid_moves().len()];
+            replay.step(&mut game, action);
+        }
+        (replay, game)
+    }
+
+    #[test]
+    fn validate_replay() {
+        let (replay, game) = record_game(5, 100);
+        assert_eq!(replay.steps.len(), game.moves);
+        assert_eq!(replay.validate(), Ok(game));
+    }
+
+    #[test]
+    fn detect_tampering() {
+        let (mut replay, _) = record_game(6, 20);
+        replay.steps[3].spawn = None;
+        assert_eq!(replay.validate(), Err(ReplayError::SpawnMismatch(3)));
+
+        let (mut replay, _) = record_game(6, 20);
+        replay.seed += 1;
+        assert!(replay.validate().is_err());
+    }
+
+    #[test]
+    fn pack_steps() {
+        let step = ReplayStep {
+            action: Actions::Down,
+            spawn: Some(Spawn {
+                row: 7,
+                col: 5,
+                value: 2,
+            }),
+        };
+        assert_eq!(ReplayStep::try_from(u16::from(step)), Ok(step));
+        let step = ReplayStep {
+            action: Actions::Left,
+            spawn: None,
+        };
+        assert_eq!(ReplayStep::try_from(u16::from(step)), Ok(step));
+        assert!(ReplayStep::try_from(1 << 10).is_err());
+    }
+
+    #[test]
+    fn save_and_load() {
+        let (replay, _) = record_game(7, 30);
+        let filename = "test_replay.json";
+        replay.save(filename).expect("Failed to save the replay");
+        let loaded = Replay::load(filename).expect("Failed to load the replay");
+        assert_eq!(replay, loaded);
+        std::fs::remove_file(filename).expect("Failed to remove test file");
+    }
+}
@@ -0,0 +1,78 @@
+use rand::{Error, RngCore, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// A SplitMix64 random number generator.
+///
+/// It is small and `Copy`, so that it can live inside a `Game`
+/// and a seed fully determines the tiles that get spawned.
+/// Its whole state is serialisable, so runs can be saved and resumed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl RngCore for SplitMix64 {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for SplitMix64 {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::seed_from_u64(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(state: u64) -> Self {
+        SplitMix64 { state }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let mut a = SplitMix64::seed_from_u64(42);
+        let mut b = SplitMix64::seed_from_u64(42);
+        let mut c = SplitMix64::seed_from_u64(43);
+        let a: Vec<u64> = (0..10).map(|_| a.gen()).collect();
+        let b: Vec<u64> = (0..10).map(|_| b.gen()).collect();
+        let c: Vec<u64> = (0..10).map(|_| c.gen()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn fill_bytes() {
+        let mut rng = SplitMix64::seed_from_u64(1);
+        let mut bytes = [0u8; 11];
+        rng.fill_bytes(&mut bytes);
+        let mut expected = SplitMix64::seed_from_u64(1);
+        assert_eq!(bytes[..8], expected.next_u64().to_le_bytes());
+        assert_eq!(bytes[8..], expected.next_u64().to_le_bytes()[..3]);
+    }
+}
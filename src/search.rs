@@ -0,0 +1,73 @@
+use crate::time::now_ms;
+use serde::{Deserialize, Serialize};
+
+/// The number of random moves played in a rollout.
+pub const DEPTH: usize = 20;
+/// The number of rollouts per move, shared among its valid actions:
+/// 200 for each of 4 actions, but 400 for each of 2.
+pub const ROLLOUTS: usize = 800;
+
+/// How much work the Monte Carlo searches do for a move.
+///
+/// A search stops after `rollouts` rollouts, or earlier when the
+/// `time_budget_ms` runs out, and returns the best move found so far.
+/// The rollouts are a budget for the whole move, not for each action,
+/// so every action gets more of them when fewer actions are valid.
+/// Without a time budget the result only depends on the rng.
+#[derive(clap::Args, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct SearchConfig {
+    /// The number of random moves played in a rollout
+    #[arg(long, default_value_t = DEPTH)]
+    pub depth: usize,
+
+    /// The maximum number of rollouts per move, shared among the valid actions
+    #[arg(long, default_value_t = ROLLOUTS)]
+    pub rollouts: usize,
+
+    /// Stop searching a move after this many milliseconds
+    #[arg(long = "time-budget")]
+    pub time_budget_ms: Option<u64>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            depth: DEPTH,
+            rollouts: ROLLOUTS,
+            time_budget_ms: None,
+        }
+    }
+}
+
+impl SearchConfig {
+    /// Starts the clock of a search.
+    pub fn deadline(&self) -> Deadline {
+        Deadline(self.time_budget_ms.map(|budget| now_ms() + budget as f64))
+    }
+}
+
+/// The wall-clock time at which a search has to stop, if any.
+#[derive(Clone, Copy, Debug)]
+pub struct Deadline(Option<f64>);
+
+impl Deadline {
+    pub fn passed(&self) -> bool {
+        self.0.is_some_and(|deadline| now_ms() >= deadline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deadlines() {
+        assert!(!SearchConfig::default().deadline().passed());
+
+        let config = SearchConfig {
+            time_budget_ms: Some(0),
+            ..Default::default()
+        };
+        assert!(config.deadline().passed());
+    }
+}
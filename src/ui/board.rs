@@ -1,13 +1,14 @@
-use crate::game::Game;
+use super::tiles::Tiles;
 use leptos::*;
 
 #[component]
-pub fn RenderBoard(game: ReadSignal<Game>) -> impl IntoView {
-    let tiles = move || game().tiles();
+pub fn RenderBoard(tiles: Signal<Tiles>) -> impl IntoView {
+    let size = move || tiles.with(|tiles| tiles.size());
+    let tiles = move || tiles.with(|tiles| tiles.tiles());
     view! {
-        <div class="game">
+        <div class="game" style=move || format!("--size: {}", size())>
             <div class="board">
-                <BoardBackground />
+                <BoardBackground size=Signal::derive(size) />
             </div>
             <div class="tiles">
                 <For
@@ -21,7 +22,8 @@ pub fn RenderBoard(game: ReadSignal<Game>) -> impl IntoView {
                             view! {
                                 <div
                                 id={index}
-                                class={move || class_name(value().0, value().1, value().2)}
+                                class={move || class_name(value().2)}
+                                style={move || position(value().0, value().1)}
                                 class:new={move || value().3}
                                 class:changed={move || value().4}
                                 >
@@ -35,36 +37,25 @@ pub fn RenderBoard(game: ReadSignal<Game>) -> impl IntoView {
     }
 }
 
-fn class_name(i: usize, j: usize, value: u32) -> String {
-    format!("col_{j} row_{i} v_{value}")
+fn class_name(value: u32) -> String {
+    format!("v_{value}")
+}
+
+fn position(i: usize, j: usize) -> String {
+    format!("--row: {i}; --col: {j}")
 }
 
 #[component]
-fn BoardBackground() -> impl IntoView {
-    view! {
-        <div>
-            <div> </div>
-            <div> </div>
-            <div> </div>
-            <div> </div>
-        </div>
-        <div>
-            <div></div>
-            <div></div>
-            <div></div>
-            <div></div>
-        </div>
-        <div>
-            <div></div>
-            <div></div>
-            <div></div>
-            <div></div>
-        </div>
-        <div>
-            <div></div>
-            <div></div>
-            <div></div>
-            <div></div>
-        </div>
+fn BoardBackground(size: Signal<usize>) -> impl IntoView {
+    move || {
+        (0..size())
+            .map(|_| {
+                view! {
+                    <div>
+                        {(0..size()).map(|_| view! { <div></div> }).collect_view()}
+                    </div>
+                }
+            })
+            .collect_view()
     }
 }
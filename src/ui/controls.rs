@@ -1,4 +1,8 @@
-use crate::game::{Actions, Game};
+use super::model::Model;
+use super::{GameState, Player, BOARD_SIZES};
+use crate::game::Actions;
+use crate::policy::{Policy, Strategy};
+use clap::ValueEnum;
 use leptos::*;
 
 use leptos_use::use_raf_fn_with_options;
@@ -7,15 +11,26 @@ use leptos_use::UseRafFnOptions;
 
 #[component]
 pub fn RenderControls() -> impl IntoView {
-    let setter = use_context::<WriteSignal<Game>>().expect("to have found the setter provided");
-    let getter = use_context::<ReadSignal<Game>>().expect("to have found the getter provided");
+    let setter =
+        use_context::<WriteSignal<GameState>>().expect("to have found the setter provided");
+    let getter = use_context::<ReadSignal<GameState>>().expect("to have found the getter provided");
+    let player = use_context::<RwSignal<Player>>().expect("to have found the player provided");
+    let policy =
+        use_context::<StoredValue<Box<dyn Policy>>>().expect("to have found the policy provided");
+    let model = use_context::<RwSignal<Model>>().expect("to have found the model provided");
+
+    let can_play = move || super::can_play(getter, player, model);
 
     let Pausable {
         pause,
         resume,
         is_active,
     } = use_raf_fn_with_options(
-        move |_| super::handle_monte_carlo(getter, setter),
+        move |_| {
+            if can_play() {
+                super::handle_policy(setter, policy)
+            }
+        },
         UseRafFnOptions::default().immediate(false),
     );
 
@@ -47,16 +62,92 @@ pub fn RenderControls() -> impl IntoView {
             inner_html="&darr;"
        />
     </div>
+    <div class="controls c-2">
+        <button
+            on:click=move |_| super::handle_undo(setter)
+            disabled=move || getter.with(|state| !state.history.can_undo())
+        >
+            Undo <br/> (Ctrl+Z)
+        </button>
+        <button
+            on:click=move |_| super::handle_redo(setter)
+            disabled=move || getter.with(|state| !state.history.can_redo())
+        >
+            Redo <br/> (Ctrl+Y)
+        </button>
+    </div>
+    <div class="controls c-1">
+        <button on:click=move |_| super::handle_download_replay(getter)>
+            Download replay
+        </button>
+    </div>
+    <div class="controls">
+        {BOARD_SIZES
+            .into_iter()
+            .map(|size| view! {
+                <button on:click=move |_| super::handle_new_game(setter, size)>
+                    {format!("{size}x{size}")}
+                </button>
+            })
+            .collect_view()}
+    </div>
+    <div class="controls c-1">
+        <select on:change=move |ev| {
+            let value = event_target_value(&ev);
+            if value == MODEL {
+                player.set(Player::Model)
+            } else if let Ok(selected) = Strategy::from_str(&value, true) {
+                player.set(Player::Strategy(selected))
+            }
+        }>
+            {Strategy::value_variants()
+                .iter()
+                .map(|&variant| Player::Strategy(variant))
+                .chain([Player::Model])
+                .map(|variant| view! {
+                    <option value=name(variant) selected=move || player() == variant>
+                        {label(variant)}
+                    </option>
+                })
+                .collect_view()}
+        </select>
+    </div>
     <div class="controls c-2">
        <button
-           on:click=move |_| super::handle_monte_carlo(getter, setter)
+           on:click=move |_| super::handle_policy(setter, policy)
+           disabled=move || !can_play()
       >
-      MCTS <br/>  1 move (Space)
+      {move || player.with(|&player| label(player))} <br/>  1 move (Space)
       </button>
-      <button on:click=move |_|
-          {if is_active() {pause()} else {resume()}}>
+      <button
+          on:click=move |_| {if is_active() {pause()} else {resume()}}
+          disabled=move || !can_play()
+      >
           {button_text}
           </button>
     </div>
     }
 }
+
+/// The value of the model in the player selection, which no strategy is named.
+const MODEL: &str = "model";
+
+fn name(player: Player) -> String {
+    match player {
+        Player::Strategy(strategy) => strategy
+            .to_possible_value()
+            .map(|value| value.get_name().to_string())
+            .unwrap_or_default(),
+        Player::Model => MODEL.to_string(),
+    }
+}
+
+fn label(player: Player) -> &'static str {
+    match player {
+        Player::Strategy(Strategy::Mcts) => "MCTS",
+        Player::Strategy(Strategy::FlatMc) => "Flat MC",
+        Player::Strategy(Strategy::Expectimax) => "Expectimax",
+        Player::Strategy(Strategy::Random) => "Random",
+        Player::Model => "Neural network",
+    }
+}
@@ -1,25 +1,153 @@
 use crate::game::*;
-use crate::mcts;
+use crate::history::GameHistory;
+use crate::policy::{Manual, Policy, Strategy};
+use crate::replay::{Replay, ReplayStep};
+use crate::search::SearchConfig;
 use leptos::*;
 use leptos_hotkeys::use_hotkeys;
 use leptos_hotkeys::{provide_hotkeys_context, scopes, HotkeysContext};
+use rand::Rng;
 
 mod board;
 use board::RenderBoard;
 
 mod controls;
 
-fn handle_step(setter: WriteSignal<Game>, action: Actions) {
-    setter.update(|game| {
-        game.step(action);
+mod model;
+use model::Model;
+
+mod settings;
+
+mod tiles;
+use tiles::Tiles;
+
+/// The number of moves that can be undone.
+const UNDO_DEPTH: usize = 1000;
+/// The default time budget of a search, short enough to keep the page responsive.
+const TIME_BUDGET_MS: u64 = 100;
+/// The sizes of the boards a new game can be started with.
+const BOARD_SIZES: [usize; 4] = [3, 4, 5, 6];
+
+/// What chooses the moves of the policy controls: a search strategy or the model.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Player {
+    Strategy(Strategy),
+    Model,
+}
+
+/// The game history together with the tile layout used to animate it,
+/// and the replay of the moves leading to the current game.
+#[derive(Clone)]
+struct GameState {
+    history: GameHistory,
+    tiles: Tiles,
+    replay: Replay,
+    undone: Vec<ReplayStep>,
+}
+
+impl GameState {
+    fn new(size: usize) -> Self {
+        let game = Game::with_size(size, rand::thread_rng().gen());
+        let tiles = Tiles::new(&game);
+        Self {
+            history: GameHistory::new(game, UNDO_DEPTH),
+            tiles,
+            replay: Replay::new(&game),
+            undone: vec![],
+        }
+    }
+
+    fn game(&self) -> &Game {
+        self.history.game()
+    }
+
+    fn step(&mut self, action: Actions) {
+        let outcome = self.history.step(action);
+        if outcome.changed {
+            self.tiles.step(&outcome);
+            self.replay.record(&outcome);
+            self.undone.clear();
+        }
+    }
+
+    fn undo(&mut self) {
+        if self.history.undo() {
+            self.tiles = Tiles::new(self.history.game());
+            self.undone.extend(self.replay.steps.pop());
+        }
+    }
+
+    fn redo(&mut self) {
+        if self.history.redo() {
+            self.tiles = Tiles::new(self.history.game());
+            self.replay.steps.extend(self.undone.pop());
+        }
+    }
+}
+
+/// Plays the move that `policy` chooses in the current game, if it has one.
+fn play_policy(setter: WriteSignal<GameState>, policy: &mut dyn Policy) {
+    setter.update(|state| {
+        if let Some(action) = policy.choose(state.game()) {
+            state.step(action);
+        }
     });
 }
 
-fn handle_monte_carlo(getter: ReadSignal<Game>, setter: WriteSignal<Game>) {
-    let next_move = mcts::simlulation(&getter.get());
-    if let Some(action) = next_move {
-        handle_step(setter, action)
-    };
+fn handle_step(setter: WriteSignal<GameState>, action: Actions) {
+    play_policy(setter, &mut Manual(action));
+}
+
+fn handle_undo(setter: WriteSignal<GameState>) {
+    setter.update(|state| state.undo());
+}
+
+fn handle_redo(setter: WriteSignal<GameState>) {
+    setter.update(|state| state.redo());
+}
+
+fn handle_download_replay(getter: ReadSignal<GameState>) {
+    let json = getter
+        .with(|state| serde_json::to_string(&state.replay))
+        .expect("Failed to serialise the replay");
+    let link = html::a();
+    link.set_href(&format!(
+        "data:application/json,{}",
+        encode_uri_component(&json)
+    ));
+    link.set_download("replay.json");
+    link.click();
+}
+
+/// Percent-encodes everything but unreserved characters, like JavaScript's `encodeURIComponent`.
+fn encode_uri_component(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+fn handle_new_game(setter: WriteSignal<GameState>, size: usize) {
+    setter.set(GameState::new(size));
+}
+
+fn handle_policy(setter: WriteSignal<GameState>, policy: StoredValue<Box<dyn Policy>>) {
+    policy.update_value(|policy| play_policy(setter, policy.as_mut()));
+}
+
+/// Returns whether `player` can play the current game,
+/// which the model can only if it takes boards of its size.
+fn can_play(
+    getter: ReadSignal<GameState>,
+    player: RwSignal<Player>,
+    model: RwSignal<Model>,
+) -> bool {
+    player() != Player::Model
+        || getter.with(|state| model.with(|model| model.network.supports(state.game().size())))
 }
 
 #[component]
@@ -27,22 +155,64 @@ pub fn RenderGame() -> impl IntoView {
     let main_ref = create_node_ref::<html::Main>();
     let HotkeysContext { .. } = provide_hotkeys_context(main_ref, false, scopes!());
 
-    let (game, set_game) = create_signal(Game::new());
-    provide_context(set_game);
-    provide_context(game);
+    let (state, set_state) = create_signal(GameState::new(DEFAULT_SIZE));
+    provide_context(set_state);
+    provide_context(state);
+
+    let player = create_rw_signal(Player::Strategy(Strategy::Mcts));
+    let config = create_rw_signal(SearchConfig {
+        time_budget_ms: Some(TIME_BUDGET_MS),
+        ..Default::default()
+    });
+    let model = create_rw_signal(Model::bundled());
+    // The policy keeps its state between moves, like the tree of the search,
+    // and is rebuilt when the player, its settings or the model change.
+    let build_policy = move || -> Box<dyn Policy> {
+        match player() {
+            Player::Strategy(strategy) => strategy.build(config(), None, rand::thread_rng().gen()),
+            Player::Model => Box::new(model.with(|model| model.network.clone())),
+        }
+    };
+    let policy = store_value(untrack(build_policy));
+    create_effect(move |_| policy.set_value(build_policy()));
+    provide_context(player);
+    provide_context(config);
+    provide_context(policy);
+    provide_context(model);
+
+    use_hotkeys!(("ArrowUp") => move |_| handle_step(set_state, Actions::Up));
+    use_hotkeys!(("ArrowDown") => move |_| handle_step(set_state, Actions::Down));
+    use_hotkeys!(("ArrowLeft") =>  move |_| handle_step(set_state, Actions::Left));
+    use_hotkeys!(("ArrowRight") =>  move |_| handle_step(set_state, Actions::Right));
+    use_hotkeys!(("Space") => move |_| {
+        if can_play(state, player, model) {
+            handle_policy(set_state, policy)
+        }
+    });
+
+    // leptos_hotkeys does not match the control modifier, so listen for Ctrl+Z / Ctrl+Y directly.
+    let _ = window_event_listener(ev::keydown, move |event| {
+        if !(event.ctrl_key() || event.meta_key()) {
+            return;
+        }
+        match event.key().to_lowercase().as_str() {
+            "z" => handle_undo(set_state),
+            "y" => handle_redo(set_state),
+            _ => return,
+        }
+        event.prevent_default();
+    });
 
-    use_hotkeys!(("ArrowUp") => move |_| handle_step(set_game, Actions::Up));
-    use_hotkeys!(("ArrowDown") => move |_| handle_step(set_game, Actions::Down));
-    use_hotkeys!(("ArrowLeft") =>  move |_| handle_step(set_game, Actions::Left));
-    use_hotkeys!(("ArrowRight") =>  move |_| handle_step(set_game, Actions::Right));
-    use_hotkeys!(("Space") => move |_| handle_monte_carlo(game, set_game));
+    let tiles = Signal::derive(move || state.with(|state| state.tiles.clone()));
 
     view! {
         <main _ref=main_ref>
             <h1> 2048</h1>
-            <div class="score">Score: {move || game().score}</div>
-            <RenderBoard game=game/>
+            <div class="score">Score: {move || state.with(|state| state.game().score)}</div>
+            <RenderBoard tiles=tiles/>
             <controls::RenderControls />
+            <model::RenderModel />
+            <settings::RenderSettings />
         </main>
     }
 }
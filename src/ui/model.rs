@@ -0,0 +1,116 @@
+use super::{GameState, BOARD_SIZES};
+use crate::game::Actions;
+use crate::nn::NeuralNetwork;
+use leptos::*;
+use strum::IntoEnumIterator;
+use wasm_bindgen_futures::JsFuture;
+
+/// The model that plays until another one is uploaded,
+/// `128.json` converted to the binary format.
+const BUNDLED_MODEL: &[u8] = include_bytes!("../../assets/model.bin");
+
+/// A neural network that plays in the browser, with the name it is shown with.
+#[derive(Clone)]
+pub struct Model {
+    pub name: String,
+    pub network: NeuralNetwork,
+}
+
+impl Model {
+    pub fn bundled() -> Self {
+        let (network, _) =
+            NeuralNetwork::from_bytes(BUNDLED_MODEL).expect("Failed to load the bundled model");
+        Self {
+            name: "Bundled model".to_string(),
+            network,
+        }
+    }
+}
+
+/// Checks that the network can play one of the board sizes of the page,
+/// taking the encoding of its boards and having one output per move.
+fn check_fits(network: &NeuralNetwork) -> Result<(), String> {
+    if BOARD_SIZES.iter().any(|&size| network.supports(size)) {
+        return Ok(());
+    }
+    Err(format!(
+        "a model with {} inputs and {} outputs does not fit any board size",
+        network.input_size(),
+        network.output_size()
+    ))
+}
+
+/// Chooses the model, and shows its outputs for the current board.
+#[component]
+pub fn RenderModel() -> impl IntoView {
+    let getter = use_context::<ReadSignal<GameState>>().expect("to have found the getter provided");
+    let model = use_context::<RwSignal<Model>>().expect("to have found the model provided");
+    let error = create_rw_signal(None::<String>);
+
+    let on_upload = move |ev: ev::Event| {
+        let input = event_target::<web_sys::HtmlInputElement>(&ev);
+        let Some(file) = input.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        spawn_local(async move {
+            let name = file.name();
+            let loaded = match JsFuture::from(file.array_buffer()).await {
+                Ok(buffer) => NeuralNetwork::decode(&js_sys::Uint8Array::new(&buffer).to_vec())
+                    .map_err(|err| err.to_string())
+                    .and_then(|(network, _)| check_fits(&network).map(|()| network)),
+                Err(_) => Err("the file could not be read".to_string()),
+            };
+            match loaded {
+                Ok(network) => {
+                    model.set(Model { name, network });
+                    error.set(None);
+                }
+                Err(err) => error.set(Some(format!("{name}: {err}"))),
+            }
+        });
+    };
+
+    // The outputs for the current board, or `None` if the model does not fit its size.
+    let outputs = move || {
+        getter.with(|state| {
+            let game = state.game();
+            model.with(|model| {
+                let network = &model.network;
+                network
+                    .supports(game.size())
+                    .then(|| (network.predict(game), network.best_move(game)))
+            })
+        })
+    };
+
+    view! {
+        <div class="model">
+            <div>{move || model.with(|model| model.name.clone())}</div>
+            <label>
+                Upload a model
+                <input type="file" accept=".json,.bin" on:change=on_upload />
+            </label>
+            <button on:click=move |_| model.set(Model::bundled())>Use the bundled model</button>
+            {move || error().map(|error| view! { <div class="error">{error}</div> })}
+            {move || match outputs() {
+                Some((outputs, best)) => view! {
+                    <table class="outputs">
+                        {Actions::iter()
+                            .map(|action| view! {
+                                <tr class:best=best == Some(action)>
+                                    <td>{format!("{action:?}")}</td>
+                                    <td>{format!("{:.3}", outputs[action as usize])}</td>
+                                </tr>
+                            })
+                            .collect_view()}
+                    </table>
+                }
+                .into_view(),
+                None => view! {
+                    <div class="error">The model does not fit the size of the board</div>
+                }
+                .into_view(),
+            }}
+        </div>
+    }
+}
@@ -0,0 +1,62 @@
+use crate::search::SearchConfig;
+use leptos::*;
+
+/// Edits the search configuration of the Monte Carlo players.
+/// The changes apply from the next move on.
+#[component]
+pub fn RenderSettings() -> impl IntoView {
+    let search =
+        use_context::<RwSignal<SearchConfig>>().expect("to have found the search config provided");
+    let config = search.get_untracked();
+
+    let update = move |f: &dyn Fn(&mut SearchConfig)| search.update(f);
+
+    view! {
+        <details class="settings">
+            <summary>Search settings</summary>
+            <label>
+                Rollout depth
+                <input
+                    type="number"
+                    min="1"
+                    value=config.depth
+                    on:change=move |ev| {
+                        if let Ok(depth) = event_target_value(&ev).parse() {
+                            update(&|config| config.depth = depth)
+                        }
+                    }
+                />
+            </label>
+            <label>
+                Rollouts per move
+                <input
+                    type="number"
+                    min="1"
+                    value=config.rollouts
+                    on:change=move |ev| {
+                        if let Ok(rollouts) = event_target_value(&ev).parse() {
+                            update(&|config| config.rollouts = rollouts)
+                        }
+                    }
+                />
+            </label>
+            <label>
+                Time budget (ms)
+                <input
+                    type="number"
+                    min="0"
+                    placeholder="none"
+                    value=config.time_budget_ms.map(|budget| budget.to_string()).unwrap_or_default()
+                    on:change=move |ev| {
+                        let budget = event_target_value(&ev);
+                        if budget.is_empty() {
+                            update(&|config| config.time_budget_ms = None)
+                        } else if let Ok(budget) = budget.parse() {
+                            update(&|config| config.time_budget_ms = Some(budget))
+                        }
+                    }
+                />
+            </label>
+        </details>
+    }
+}
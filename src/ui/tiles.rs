@@ -0,0 +1,96 @@
+use crate::game::{Game, Merge, MoveOutcome, TileMove, MAX_SIZE};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Tile {
+    idx: u32,
+    value: u32,
+    new: bool,
+    changed: bool,
+}
+
+/// Keeps a stable index for every tile on the board, so that
+/// the renderer can animate tiles sliding from cell to cell.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Tiles {
+    board: [[Option<Tile>; MAX_SIZE]; MAX_SIZE],
+    size: usize,
+    count: u32,
+}
+
+impl Tiles {
+    pub fn new(game: &Game) -> Self {
+        let mut tiles = Tiles {
+            board: [[None; MAX_SIZE]; MAX_SIZE],
+            size: game.size(),
+            count: 0,
+        };
+        for (i, row) in tiles.board.iter_mut().enumerate().take(tiles.size) {
+            for (j, tile) in row.iter_mut().enumerate().take(tiles.size) {
+                let value = game.get(i, j);
+                if value > 0 {
+                    tiles.count += 1;
+                    *tile = Some(Tile {
+                        idx: tiles.count,
+                        value,
+                        new: true,
+                        changed: false,
+                    });
+                }
+            }
+        }
+        tiles
+    }
+
+    /// The number of rows (and columns) of the board.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Moves the tiles as described by the outcome of a step.
+    /// Of two merged tiles, the one closer to the edge keeps its index.
+    pub fn step(&mut self, outcome: &MoveOutcome) {
+        let mut board = [[None; MAX_SIZE]; MAX_SIZE];
+        for TileMove { from, to } in outcome.movements() {
+            if let (Some(mut tile), None) = (self.board[from.0][from.1], board[to.0][to.1]) {
+                tile.new = false;
+                tile.changed = false;
+                board[to.0][to.1] = Some(tile);
+            }
+        }
+        for Merge { at, value } in outcome.merges() {
+            if let Some(tile) = &mut board[at.0][at.1] {
+                tile.value = value;
+                tile.changed = true;
+            }
+        }
+        if let Some(spawn) = outcome.spawn {
+            self.count += 1;
+            board[spawn.row][spawn.col] = Some(Tile {
+                idx: self.count,
+                value: spawn.value,
+                new: true,
+                changed: false,
+            });
+        }
+        self.board = board;
+    }
+
+    /// Return the hashmap of all tiles
+    /// index is a unique id, then i, j and value
+    pub fn tiles(&self) -> BTreeMap<u32, (usize, usize, u32, bool, bool)> {
+        let mut hm: BTreeMap<u32, (usize, usize, u32, bool, bool)> = BTreeMap::new();
+
+        self.board.iter().enumerate().for_each(|(i, row)| {
+            row.iter().enumerate().for_each(|(j, tile)| {
+                if let Some(tile) = tile {
+                    hm.insert(
+                        tile.idx,
+                        (i, j, u32::pow(2, tile.value), tile.new, tile.changed),
+                    );
+                }
+            })
+        });
+        hm
+    }
+}